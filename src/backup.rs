@@ -0,0 +1,426 @@
+//! Deciding whether the work in a repo would survive losing this device.
+
+use crate::{BranchStatus, FileChange, Head, Operation, RepoStatus};
+
+use serde::{Serialize, Deserialize};
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Which conditions are tolerated when deciding if a repo is backed up.
+#[derive(Clone, Debug, Default)]
+pub struct BackupPolicy {
+    /// Kinds of uncommitted file changes that don't count against the repo.
+    pub allow_dirty: Vec<FileChange>,
+    /// Fail repos whose remotes are all on the same filesystem, rather than only warning about
+    /// them. See `RemoteDistance::is_off_device` for what can't be detected.
+    pub require_off_device: bool,
+    /// Warn about remotes that weren't fetched from in this many seconds, since what is known
+    /// about them may be out of date.
+    pub max_fetch_age: Option<u64>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all="snake_case")]
+pub struct BackupVerdict {
+    pub backed_up: bool,
+    /// Reasons the repo is not backed up; empty if `backed_up` is true.
+    pub problems: Vec<BackupProblem>,
+    /// Work that is at risk but does not fail the check under the policy.
+    pub warnings: Vec<BackupProblem>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag="kind", rename_all="snake_case")]
+pub enum BackupProblem {
+    UncommittedChanges {
+        changes: BTreeMap<FileChange, usize>,
+    },
+    /// A merge, rebase or similar has to be finished or aborted first.
+    OperationInProgress {
+        operation: Operation,
+    },
+    NoRemotes,
+    /// Every remote is on the same filesystem as the repo, or couldn't be located.
+    NoOffDeviceRemote,
+    /// A tracking branch has commits that are not on its upstream.
+    UnpushedCommits {
+        branch: String,
+        upstream: String,
+        ahead: usize,
+    },
+    /// A tracking branch shares no history with its upstream.
+    UnrelatedUpstream {
+        branch: String,
+        upstream: String,
+    },
+    /// The remote no longer has what the local copy of a tracking branch's upstream says, so it
+    /// isn't known whether the branch is pushed.
+    StaleUpstream {
+        branch: String,
+        upstream: String,
+    },
+    /// A remote that wasn't fetched from within `BackupPolicy::max_fetch_age`.
+    OldFetch {
+        remote: String,
+        /// Seconds since the last fetch, or `None` if it was never fetched from.
+        age: Option<u64>,
+    },
+    /// A remote that couldn't be reached to verify its refs.
+    UnverifiedRemote {
+        remote: String,
+        error: String,
+    },
+    /// A branch with no upstream that is not contained in any remote branch.
+    LocalBranch {
+        branch: String,
+    },
+    /// A branch that a remote fetches into directly, as in a mirror clone, which wasn't verified
+    /// to match the remote.
+    UnverifiedMirrorBranch {
+        branch: String,
+        remote: String,
+    },
+    /// HEAD is detached at a commit that is not contained in any remote branch.
+    DetachedHead {
+        commit: String,
+    },
+    /// A tag that may not be on any remote.
+    UnpushedTag(crate::UnpushedTag),
+    /// The superproject records a submodule commit that is not in any remote branch of the
+    /// submodule, so nobody else can check it out.
+    UnpushedSubmoduleCommit {
+        path: String,
+        commit: String,
+    },
+    /// A submodule that is checked out but couldn't be read.
+    UnreadableSubmodule {
+        path: String,
+        error: String,
+    },
+    /// A problem with the work in a submodule.
+    InSubmodule {
+        path: String,
+        problem: Box<BackupProblem>,
+    },
+    /// A linked worktree that exists but couldn't be read.
+    UnreadableWorktree {
+        path: String,
+        error: String,
+    },
+    /// A problem with the files, HEAD or operation in progress of a linked worktree.
+    InWorktree {
+        path: String,
+        problem: Box<BackupProblem>,
+    },
+    /// The repo uses Git LFS but pushes don't upload LFS objects, since the hook that does isn't
+    /// installed.
+    MissingLfsHook,
+    /// LFS objects stored locally that no remote is known to have.
+    UnpushedLfsObjects {
+        objects: usize,
+        /// Total size of the objects in bytes.
+        size: u64,
+    },
+    /// A commit only reflogs refer to, which `git gc` deletes once the entries expire.
+    ReflogCommit {
+        commit: String,
+        summary: String,
+        /// Seconds since the commit was made.
+        age: u64,
+    },
+    /// A stash, which is never pushed.
+    Stash(crate::Stash),
+}
+
+impl fmt::Display for BackupProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use BackupProblem::*;
+        match self {
+            UncommittedChanges { changes } => {
+                let changes = changes.iter()
+                    .map(|(change, count)| format!("{} {}", count, change.as_str()))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                write!(f, "uncommitted changes ({})", changes)
+            },
+            OperationInProgress { operation } => {
+                write!(f, "{} in progress", operation.kind.as_str())?;
+                if let Some(branch) = &operation.branch {
+                    write!(f, " on {}", branch)?;
+                }
+                if let Some(progress) = &operation.progress {
+                    write!(f, " (step {} of {})", progress.step, progress.total)?;
+                }
+                if !operation.conflicted.is_empty() {
+                    write!(f, ", {} conflicted files", operation.conflicted.len())?;
+                }
+                Ok(())
+            },
+            NoRemotes => write!(f, "no remotes"),
+            NoOffDeviceRemote => write!(f, "no remote is on another filesystem or host"),
+            UnpushedCommits { branch, upstream, ahead } =>
+                write!(f, "{}: {} commits not pushed to {}", branch, ahead, upstream),
+            UnrelatedUpstream { branch, upstream } =>
+                write!(f, "{}: no common history with {}", branch, upstream),
+            StaleUpstream { branch, upstream } =>
+                write!(f, "{}: {} is out of date with the remote, fetch to check", branch, upstream),
+            OldFetch { remote, age: Some(age) } =>
+                write!(f, "{}: last fetched {} ago", remote, Age(*age)),
+            OldFetch { remote, age: None } => write!(f, "{}: never fetched", remote),
+            UnverifiedRemote { remote, error } =>
+                write!(f, "{}: could not verify remote: {}", remote, error),
+            LocalBranch { branch } => write!(f, "{}: local branch is not in any remote", branch),
+            UnverifiedMirrorBranch { branch, remote } =>
+                write!(f, "{}: not verified to match {}, verify remotes to check", branch, remote),
+            DetachedHead { commit } =>
+                write!(f, "HEAD is detached at {}, which is not in any remote", commit),
+            UnpushedTag(tag) => {
+                let mut reasons = Vec::new();
+                if !tag.merged_in_remote {
+                    reasons.push("not in any remote branch".to_string());
+                }
+                if !tag.missing_on.is_empty() {
+                    reasons.push(format!("missing on {}", tag.missing_on.join(", ")));
+                }
+                if !tag.differs_on.is_empty() {
+                    reasons.push(format!("different on {}", tag.differs_on.join(", ")));
+                }
+                write!(f, "tag {}: {}", tag.name, reasons.join("; "))
+            },
+            UnpushedSubmoduleCommit { path, commit } =>
+                write!(f, "{}: recorded submodule commit {} is not in any remote", path, &commit[..10]),
+            UnreadableSubmodule { path, error } =>
+                write!(f, "{}: could not read submodule: {}", path, error),
+            InSubmodule { path, problem } => write!(f, "{}: {}", path, problem),
+            UnreadableWorktree { path, error } =>
+                write!(f, "{}: could not read worktree: {}", path, error),
+            InWorktree { path, problem } => write!(f, "{}: {}", path, problem),
+            MissingLfsHook =>
+                write!(f, "uses Git LFS but the pre-push hook isn't installed, run `git lfs install`"),
+            UnpushedLfsObjects { objects, size } =>
+                write!(f, "{} LFS objects ({} bytes) may only be stored locally", objects, size),
+            ReflogCommit { commit, summary, age } =>
+                write!(f, "{}: only in reflogs, \"{}\" from {} ago", &commit[..10], summary, Age(*age)),
+            Stash(stash) =>
+                write!(f, "stash@{{{}}}: {} ({} files)", stash.index, stash.message, stash.files),
+        }
+    }
+}
+
+/// Formats a number of seconds in the largest whole unit.
+struct Age(u64);
+
+impl fmt::Display for Age {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let units = [(86400, "days"), (3600, "hours"), (60, "minutes")];
+        for (seconds, unit) in &units {
+            if self.0 >= *seconds {
+                return write!(f, "{} {}", self.0 / seconds, unit);
+            }
+        }
+        write!(f, "{} seconds", self.0)
+    }
+}
+
+pub fn check_backup(status: &RepoStatus, policy: &BackupPolicy) -> BackupVerdict {
+    let mut problems = Vec::new();
+    let mut warnings = Vec::new();
+
+    if !status.files.is_clean_except(&policy.allow_dirty) {
+        problems.push(BackupProblem::UncommittedChanges {
+            changes: status.files.changes().collect(),
+        });
+    }
+
+    if let Some(operation) = &status.operation {
+        problems.push(BackupProblem::OperationInProgress {
+            operation: operation.clone(),
+        });
+    }
+
+    if status.remotes.is_empty() {
+        problems.push(BackupProblem::NoRemotes);
+    } else if !status.remotes.iter().any(|remote| remote.distance.is_off_device()) {
+        if policy.require_off_device {
+            problems.push(BackupProblem::NoOffDeviceRemote);
+        } else {
+            warnings.push(BackupProblem::NoOffDeviceRemote);
+        }
+    }
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|now| now.as_secs() as i64)
+        .unwrap_or(0);
+    for remote in &status.remotes {
+        let verified = match &remote.verification {
+            Some(verification) => match &verification.error {
+                Some(error) => {
+                    warnings.push(BackupProblem::UnverifiedRemote {
+                        remote: remote.name.clone(),
+                        error: error.clone(),
+                    });
+                    false
+                },
+                None => true,
+            },
+            None => false,
+        };
+
+        // a verified remote was just checked, however long ago it was fetched
+        let max_age = match policy.max_fetch_age {
+            Some(max_age) if !verified => max_age,
+            _ => continue,
+        };
+        let age = remote.last_fetch.map(|last_fetch| (now - last_fetch).max(0) as u64);
+        if age.is_none_or(|age| age > max_age) {
+            warnings.push(BackupProblem::OldFetch {
+                remote: remote.name.clone(),
+                age,
+            });
+        }
+    }
+
+    // check all the branches are up to date tracking branches or merged local branches
+    let mut names: Vec<_> = status.branches.keys().collect();
+    names.sort();
+    for name in names {
+        let branch_status = &status.branches[name];
+        if let BranchStatus::TrackingBranch(tracking) = branch_status {
+            if tracking.stale {
+                problems.push(BackupProblem::StaleUpstream {
+                    branch: name.clone(),
+                    upstream: tracking.upstream.clone(),
+                });
+                continue;
+            }
+        }
+        if branch_status.merged_in_upstream() {
+            continue;
+        }
+
+        let branch = name.clone();
+        problems.push(match branch_status {
+            BranchStatus::TrackingBranch(tracking) if tracking.unrelated => {
+                BackupProblem::UnrelatedUpstream {
+                    branch,
+                    upstream: tracking.upstream.clone(),
+                }
+            },
+            BranchStatus::TrackingBranch(tracking) => BackupProblem::UnpushedCommits {
+                branch,
+                upstream: tracking.upstream.clone(),
+                ahead: tracking.ahead,
+            },
+            BranchStatus::MirrorBranch { remote, .. } => BackupProblem::UnverifiedMirrorBranch {
+                branch,
+                remote: remote.clone(),
+            },
+            _ => BackupProblem::LocalBranch { branch },
+        });
+    }
+
+    if let Head::Detached { commit, merged_in_remote: false } = &status.head {
+        problems.push(BackupProblem::DetachedHead {
+            commit: commit.clone(),
+        });
+    }
+
+    problems.extend(status.unpushed_tags.iter().cloned().map(BackupProblem::UnpushedTag));
+
+    for submodule in &status.submodules {
+        let path = &submodule.path;
+        if let Some(error) = &submodule.error {
+            warnings.push(BackupProblem::UnreadableSubmodule {
+                path: path.clone(),
+                error: error.clone(),
+            });
+        }
+        if let (Some(commit), Some(false)) = (&submodule.recorded, submodule.recorded_in_remote) {
+            problems.push(BackupProblem::UnpushedSubmoduleCommit {
+                path: path.clone(),
+                commit: commit.clone(),
+            });
+        }
+
+        let sub_status = match &submodule.status {
+            Some(sub_status) => sub_status,
+            None => continue,
+        };
+        let verdict = check_backup(sub_status, policy);
+        let wrap = |problems: Vec<BackupProblem>| problems.into_iter()
+            // a submodule is normally detached at the recorded commit, which is reported above
+            .filter(|problem| match problem {
+                BackupProblem::DetachedHead { commit } =>
+                    submodule.recorded.as_ref() != Some(commit),
+                _ => true,
+            })
+            .map(|problem| BackupProblem::InSubmodule {
+                path: path.clone(),
+                problem: Box::new(problem),
+            });
+        problems.extend(wrap(verdict.problems));
+        warnings.extend(wrap(verdict.warnings));
+    }
+
+    // branches, tags and stashes are shared with the repo and checked above
+    for worktree in &status.worktrees {
+        let path = &worktree.path;
+        if let Some(error) = &worktree.error {
+            warnings.push(BackupProblem::UnreadableWorktree {
+                path: path.clone(),
+                error: error.clone(),
+            });
+        }
+
+        let mut worktree_problems = Vec::new();
+        if !worktree.files.is_clean_except(&policy.allow_dirty) {
+            worktree_problems.push(BackupProblem::UncommittedChanges {
+                changes: worktree.files.changes().collect(),
+            });
+        }
+        if let Some(operation) = &worktree.operation {
+            worktree_problems.push(BackupProblem::OperationInProgress {
+                operation: operation.clone(),
+            });
+        }
+        if let Some(Head::Detached { commit, merged_in_remote: false }) = &worktree.head {
+            worktree_problems.push(BackupProblem::DetachedHead {
+                commit: commit.clone(),
+            });
+        }
+        problems.extend(worktree_problems.into_iter().map(|problem| BackupProblem::InWorktree {
+            path: path.clone(),
+            problem: Box::new(problem),
+        }));
+    }
+
+    if let Some(lfs) = &status.lfs {
+        if !lfs.pre_push_hook {
+            problems.push(BackupProblem::MissingLfsHook);
+        }
+        if !lfs.unpushed_objects.is_empty() {
+            problems.push(BackupProblem::UnpushedLfsObjects {
+                objects: lfs.unpushed_objects.len(),
+                size: lfs.unpushed_objects.iter().map(|object| object.size).sum(),
+            });
+        }
+    }
+
+    for commit in status.reflog_commits.iter().flatten() {
+        warnings.push(BackupProblem::ReflogCommit {
+            commit: commit.id.clone(),
+            summary: commit.summary.clone(),
+            age: (now - commit.time).max(0) as u64,
+        });
+    }
+    warnings.extend(status.stashes.iter().cloned().map(BackupProblem::Stash));
+
+    BackupVerdict {
+        backed_up: problems.is_empty(),
+        problems,
+        warnings,
+    }
+}
@@ -0,0 +1,97 @@
+//! Collecting the status of many repositories concurrently.
+
+use crate::{get_status_with_options, open_repo, Error, RepoStatus, StatusOptions};
+
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+
+/// Results of `map_repos`, yielded in the order the repos finish.
+pub struct BatchStream<T> {
+    results: mpsc::Receiver<(PathBuf, T)>,
+    workers: Vec<thread::JoinHandle<()>>,
+}
+
+/// Results of `collect_statuses`, yielded in the order the repos finish.
+pub type StatusStream = BatchStream<Result<RepoStatus, Error>>;
+
+impl<T> Iterator for BatchStream<T> {
+    type Item = (PathBuf, T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.results.recv() {
+            Ok(result) => Some(result),
+            Err(_) => {
+                // every worker has hung up, so make sure none of them died along the way
+                for worker in self.workers.drain(..) {
+                    if let Err(panic) = worker.join() {
+                        std::panic::resume_unwind(panic);
+                    }
+                }
+                None
+            },
+        }
+    }
+}
+
+/// Get the status of every repo in `paths` using at most `workers` threads.
+///
+/// If `workers` is 0 the number of available CPUs is used. A repo that fails is reported with its
+/// error and does not stop the rest of the batch.
+pub fn collect_statuses<I>(paths: I, options: &StatusOptions, workers: usize) -> StatusStream
+where
+    I: IntoIterator<Item=PathBuf>,
+{
+    let options = options.clone();
+    map_repos(paths, workers, move |path| {
+        open_repo(path).and_then(|mut repo| get_status_with_options(&mut repo, &options))
+    })
+}
+
+/// Call `f` on every path in `paths` using at most `workers` threads.
+///
+/// `workers` is interpreted the same way as for `collect_statuses`.
+pub fn map_repos<I, T, F>(paths: I, workers: usize, f: F) -> BatchStream<T>
+where
+    I: IntoIterator<Item=PathBuf>,
+    T: Send + 'static,
+    F: Fn(&Path) -> T + Send + Sync + 'static,
+{
+    let paths: Vec<PathBuf> = paths.into_iter().collect();
+    let workers = if workers == 0 {
+        thread::available_parallelism().map(|n| n.get()).unwrap_or(1)
+    } else {
+        workers
+    };
+    let workers = workers.min(paths.len()).max(1);
+
+    let queue = Arc::new(Mutex::new(paths.into_iter()));
+    let f = Arc::new(f);
+    let (sender, results) = mpsc::channel();
+
+    let workers = (0..workers)
+        .map(|_| {
+            let queue = Arc::clone(&queue);
+            let sender = sender.clone();
+            let f = Arc::clone(&f);
+            thread::spawn(move || loop {
+                let path = match queue.lock().unwrap().next() {
+                    Some(path) => path,
+                    None => break,
+                };
+
+                let result = f(&path);
+
+                // the receiver is gone, so nobody wants the rest of the results
+                if sender.send((path, result)).is_err() {
+                    break;
+                }
+            })
+        })
+        .collect();
+
+    BatchStream {
+        results,
+        workers,
+    }
+}
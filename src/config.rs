@@ -1,3 +1,5 @@
+use crate::Error;
+
 use uuid::Uuid;
 use serde::{Serialize, Deserialize};
 
@@ -13,6 +15,8 @@ use std::io::{
     BufWriter,
 };
 use std::env;
+use std::ffi::OsStr;
+use std::fmt;
 
 // TODO: Version handling. Current thought is to create a `version` file, but
 // only check if it serializing/deserializing fails... seems annoying to read
@@ -21,6 +25,8 @@ use std::env;
 const DEVICE_ID_PATH: &str = "deviceid";
 const HUB_ID_PATH: &str = "hub";
 const DEVICE_CONFIG_DIR: &str = "device";
+const INDEX_EXTENSION: &str = "index.json";
+#[allow(dead_code)]
 const HUB_CONFIG_DIR: &str = "hubs";
 
 const DEFAULT_HUB: &str = "default";
@@ -32,8 +38,12 @@ pub struct Config {
 }
 
 impl Config {
-    pub fn load<P: AsRef<Path>>(path: P) -> Result<Config, ConfigError> {
-        let mut path = PathBuf::from(path.as_ref());
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Config, Error> {
+        Ok(Config::read(path.as_ref())?)
+    }
+
+    fn read(path: &Path) -> Result<Config, ConfigError> {
+        let mut path = PathBuf::from(path);
 
         path.push(DEVICE_ID_PATH);
         let id = fs::read_to_string(&path)?;
@@ -60,8 +70,12 @@ impl Config {
     /// Initialize configuration for this device
     ///
     /// Returns Ok(None) if the device appears to already be initialized.
-    pub fn init<P: AsRef<Path>>(path: P) -> Result<Option<Config>, ConfigError> {
-        let mut path = PathBuf::from(path.as_ref());
+    pub fn init<P: AsRef<Path>>(path: P) -> Result<Option<Config>, Error> {
+        Ok(Config::create(path.as_ref())?)
+    }
+
+    fn create(path: &Path) -> Result<Option<Config>, ConfigError> {
+        let mut path = PathBuf::from(path);
         path.push(DEVICE_ID_PATH);
         if path.exists() {
             // TODO: do a more fine grained check on what the problem is
@@ -89,8 +103,12 @@ impl Config {
         }))
     }
 
-    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), ConfigError> {
-        let mut path = PathBuf::from(path.as_ref());
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        Ok(self.write(path.as_ref())?)
+    }
+
+    fn write(&self, path: &Path) -> Result<(), ConfigError> {
+        let mut path = PathBuf::from(path);
         Config::device_config_path(&mut path, &self.device.hub);
 
         // TODO: More safety... save a backup...
@@ -99,41 +117,145 @@ impl Config {
         Ok(())
     }
 
-    pub fn star<P1: AsRef<Path>, P2: AsRef<Path>>(&mut self, path: P1, alias: Option<P2>) -> bool {
-        let path = path.as_ref();
-        if !path.exists() || !path.is_dir() {
-            return false;
-        }
+    /// Star a directory, using its name as the alias if none is given.
+    ///
+    /// Returns the alias used. Starring the same directory under the same alias again does
+    /// nothing, but an alias that is already used for a different directory is an error.
+    pub fn star<P1: AsRef<Path>, P2: AsRef<OsStr>>(
+        &mut self,
+        path: P1,
+        alias: Option<P2>,
+    ) -> Result<String, Error> {
+        let path = starrable_dir(path.as_ref())?;
 
         let alias = match alias {
-            Some(alias) =>
-                if let Some(alias) = alias.as_ref().to_str() {
-                    alias.to_string()
-                } else {
-                    return false
-                },
-            None => {
-                let dir = path.file_name().unwrap();
-                if let Some(alias) = dir.to_str() {
-                    alias.to_string()
-                } else {
-                    return false
-                }
-            }
+            Some(alias) => alias_str(alias.as_ref())?,
+            None => match path.file_name() {
+                Some(name) => alias_str(name)?,
+                None => return Err(ConfigError::NoDefaultAlias(path).into()),
+            },
         };
 
-        self.device.config.starred.insert(alias, StoredPath::from(path));
+        if let Some(existing) = self.device.config.starred.get(&alias) {
+            if existing.as_path() != path {
+                return Err(ConfigError::AliasTaken {
+                    alias,
+                    path: existing.as_path().to_path_buf(),
+                }.into());
+            }
+        }
+
+        self.device.config.starred.insert(alias.clone(), StoredPath::from(path));
+
+        Ok(alias)
+    }
+
+    /// Remove a starred directory, returning the path it pointed to.
+    pub fn unstar<S: AsRef<OsStr>>(&mut self, alias: S) -> Result<PathBuf, Error> {
+        let alias = alias_str(alias.as_ref())?;
+        match self.device.config.starred.remove(&alias) {
+            Some(path) => Ok(path.as_path().to_path_buf()),
+            None => Err(ConfigError::UnknownAlias(alias).into()),
+        }
+    }
+
+    /// Give a starred directory a new alias.
+    pub fn rename_star<S1: AsRef<OsStr>, S2: AsRef<OsStr>>(
+        &mut self,
+        alias: S1,
+        new_alias: S2,
+    ) -> Result<(), Error> {
+        let alias = alias_str(alias.as_ref())?;
+        let new_alias = alias_str(new_alias.as_ref())?;
+        if !self.device.config.starred.contains_key(&alias) {
+            return Err(ConfigError::UnknownAlias(alias).into());
+        }
+        if new_alias == alias {
+            return Ok(());
+        }
+        if let Some(existing) = self.device.config.starred.get(&new_alias) {
+            return Err(ConfigError::AliasTaken {
+                alias: new_alias,
+                path: existing.as_path().to_path_buf(),
+            }.into());
+        }
+
+        let path = self.device.config.starred.remove(&alias).unwrap();
+        self.device.config.starred.insert(new_alias, path);
+
+        Ok(())
+    }
+
+    /// Point an existing alias at a different directory, e.g. after the directory was moved.
+    pub fn relocate_star<S: AsRef<OsStr>, P: AsRef<Path>>(
+        &mut self,
+        alias: S,
+        path: P,
+    ) -> Result<(), Error> {
+        let alias = alias_str(alias.as_ref())?;
+        let path = starrable_dir(path.as_ref())?;
+        match self.device.config.starred.get_mut(&alias) {
+            Some(stored) => {
+                *stored = StoredPath::from(path);
+                Ok(())
+            },
+            None => Err(ConfigError::UnknownAlias(alias).into()),
+        }
+    }
+
+    /// Starred directories and their aliases, sorted by alias.
+    pub fn starred(&self) -> Vec<(&str, &Path)> {
+        let mut starred: Vec<_> = self.device.config.starred.iter()
+            .map(|(alias, path)| (alias.as_str(), path.as_path()))
+            .collect();
+        starred.sort();
+        starred
+    }
+
+    /// The directory starred under `alias`, if there is one.
+    pub fn starred_path(&self, alias: &str) -> Option<&Path> {
+        self.device.config.starred.get(alias)
+            .map(|path| path.as_path())
+    }
 
-        true
+    /// Where the repo index for this device is saved, see `index::Index`.
+    pub fn index_path<P: AsRef<Path>>(&self, path: P) -> PathBuf {
+        let mut path = path.as_ref().join(DEVICE_CONFIG_DIR);
+        path.push(&self.device.hub);
+        path.set_extension(INDEX_EXTENSION);
+        path
     }
 
     fn device_config_path(config_path: &mut PathBuf, hub: &str) {
         config_path.push(DEVICE_CONFIG_DIR);
-        config_path.push(&hub);
+        config_path.push(hub);
         config_path.set_extension("json");
     }
 }
 
+/// Check `path` is a directory and make it absolute.
+///
+/// Starred directories are used from any working directory, so relative paths are never stored.
+/// Neither are paths that aren't valid UTF-8, since `StoredPath` would mangle them.
+fn starrable_dir(path: &Path) -> Result<PathBuf, Error> {
+    if !path.is_dir() {
+        return Err(ConfigError::NotADirectory(path.to_path_buf()).into());
+    }
+
+    let path = fs::canonicalize(path).map_err(|err| Error::Io(path.to_path_buf(), err))?;
+    if path.to_str().is_none() {
+        return Err(Error::NonUtf8Name(path.to_string_lossy().into_owned()));
+    }
+    Ok(path)
+}
+
+fn alias_str(alias: &OsStr) -> Result<String, Error> {
+    match alias.to_str() {
+        Some(alias) => Ok(alias.to_string()),
+        None => Err(Error::NonUtf8Name(alias.to_string_lossy().into_owned())),
+    }
+}
+
 #[derive(Debug)]
 struct Device {
     id: String,
@@ -169,6 +291,12 @@ struct DeviceConfig {
 #[serde(transparent)]
 struct StoredPath(String);
 
+impl StoredPath {
+    fn as_path(&self) -> &Path {
+        Path::new(&self.0)
+    }
+}
+
 impl<P: AsRef<Path>> From<P> for StoredPath {
     fn from(path: P) -> StoredPath {
         StoredPath(path.as_ref().to_string_lossy().into_owned())
@@ -178,8 +306,54 @@ impl<P: AsRef<Path>> From<P> for StoredPath {
 #[derive(Debug)]
 pub enum ConfigError {
     NotFound,
+    /// Neither HOME or VIRTUAL_REPO_HUB_HOME was defined.
+    NoConfigDir,
     Io(io::Error),
     Parsing(serde_json::Error),
+    /// The alias is already used for a different starred directory.
+    AliasTaken {
+        alias: String,
+        path: PathBuf,
+    },
+    /// No directory is starred under the alias.
+    UnknownAlias(String),
+    /// A directory was expected, but the path is missing or is something else.
+    NotADirectory(PathBuf),
+    /// No alias was given and the directory has no name to use instead, e.g. `/`.
+    NoDefaultAlias(PathBuf),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::NotFound => write!(f, "configuration not found"),
+            ConfigError::NoConfigDir =>
+                write!(f, "neither HOME or VIRTUAL_REPO_HUB_HOME was defined"),
+            ConfigError::Io(err) => write!(f, "{}", err),
+            ConfigError::Parsing(err) => write!(f, "failed to parse configuration: {}", err),
+            ConfigError::AliasTaken { alias, path } =>
+                write!(f, "alias {:?} is already used for {}", alias, path.display()),
+            ConfigError::UnknownAlias(alias) => write!(f, "no directory is starred as {:?}", alias),
+            ConfigError::NotADirectory(path) => write!(f, "not a directory: {}", path.display()),
+            ConfigError::NoDefaultAlias(path) =>
+                write!(f, "{} has no name to use as an alias, give one explicitly", path.display()),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConfigError::Io(err) => Some(err),
+            ConfigError::Parsing(err) => Some(err),
+            ConfigError::NotFound
+            | ConfigError::NoConfigDir
+            | ConfigError::AliasTaken { .. }
+            | ConfigError::UnknownAlias(_)
+            | ConfigError::NotADirectory(_)
+            | ConfigError::NoDefaultAlias(_) => None,
+        }
+    }
 }
 
 impl From<io::Error> for ConfigError {
@@ -198,9 +372,7 @@ impl From<serde_json::Error> for ConfigError {
     }
 }
 
-pub fn config_path() -> Result<PathBuf, &'static str> {
-    const ERR: &str = "Neither HOME or VIRTUAL_REPO_HUB_HOME was defined";
-
+pub fn config_path() -> Result<PathBuf, Error> {
     Ok(match path_var("VIRTUAL_REPO_HUB_HOME") {
         Some(path) => path,
         None => match path_var("HOME") {
@@ -215,12 +387,12 @@ pub fn config_path() -> Result<PathBuf, &'static str> {
                 }
                 path
             },
-            None => return Err(ERR),
+            None => return Err(ConfigError::NoConfigDir.into()),
         },
     })
 }
 
-fn path_var<'a, 'b>(key: &'a str) -> Option<PathBuf> {
+fn path_var(key: &str) -> Option<PathBuf> {
     use env::VarError as E;
 
     Some(match env::var(key) {
@@ -277,13 +449,13 @@ mod test {
         env::set_var("HOME", "tests");
 
         let mut p = config_path().unwrap();
-        assert_eq!(PathBuf::from("tests/.config/virtual_repo_hub"), p);
+        assert_eq!(PathBuf::from("tests/.config/virtual-repo-hub"), p);
 
-        // .virtual_repo_hub is used when .config doesn't exist
+        // .virtual-repo-hub is used when .config doesn't exist
         env::set_var("HOME", "fakehome");
 
         p = config_path().unwrap();
-        assert_eq!(PathBuf::from("fakehome/.virtual_repo_hub"), p);
+        assert_eq!(PathBuf::from("fakehome/.virtual-repo-hub"), p);
 
         // env var overrides everything when used
         env::set_var("VIRTUAL_REPO_HUB_HOME", "foo/bar/baz");
@@ -291,4 +463,74 @@ mod test {
         p = config_path().unwrap();
         assert_eq!(PathBuf::from("foo/bar/baz"), p);
     }
+
+    #[test]
+    fn manages_stars() {
+        let tmp = tempfile::tempdir().unwrap();
+        let work = tmp.path().join("work");
+        let moved = tmp.path().join("moved");
+        fs::create_dir(&work).unwrap();
+        fs::create_dir(&moved).unwrap();
+        let work = fs::canonicalize(work).unwrap();
+        let moved = fs::canonicalize(moved).unwrap();
+
+        let mut config = Config { device: Device::new() };
+        assert_eq!("work", config.star(&work, None::<&str>).unwrap());
+        // starring the same directory again is fine, but not reusing the alias for another one
+        assert_eq!("work", config.star(&work, None::<&str>).unwrap());
+        match config.star(&moved, Some("work")) {
+            Err(Error::Config(ConfigError::AliasTaken { alias, path })) => {
+                assert_eq!("work", alias);
+                assert_eq!(work, path);
+            },
+            other => panic!("expected alias collision, got {:?}", other),
+        }
+        match config.star(tmp.path().join("missing"), None::<&str>) {
+            Err(Error::Config(ConfigError::NotADirectory(_))) => {},
+            other => panic!("expected missing directory, got {:?}", other),
+        }
+
+        config.rename_star("work", "job").unwrap();
+        assert_eq!(vec![("job", work.as_path())], config.starred());
+
+        config.relocate_star("job", &moved).unwrap();
+        assert_eq!(Some(moved.as_path()), config.starred_path("job"));
+
+        assert_eq!(moved, config.unstar("job").unwrap());
+        match config.unstar("job") {
+            Err(Error::Config(ConfigError::UnknownAlias(_))) => {},
+            other => panic!("expected unknown alias, got {:?}", other),
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rejects_non_utf8_aliases() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let tmp = tempfile::tempdir().unwrap();
+        let alias = OsStr::from_bytes(b"\xff");
+        let mut config = Config { device: Device::new() };
+        let is_non_utf8 = |result: Result<(), Error>| matches!(result, Err(Error::NonUtf8Name(_)));
+        assert!(is_non_utf8(config.star(tmp.path(), Some(alias)).map(drop)));
+        assert!(is_non_utf8(config.unstar(alias).map(drop)));
+        assert!(is_non_utf8(config.rename_star(alias, "job")));
+        assert!(is_non_utf8(config.relocate_star(alias, tmp.path())));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rejects_non_utf8_paths() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().join(OsStr::from_bytes(b"\xff"));
+        fs::create_dir(&dir).unwrap();
+        let mut config = Config { device: Device::new() };
+        let is_non_utf8 = |result: Result<(), Error>| matches!(result, Err(Error::NonUtf8Name(_)));
+        assert!(is_non_utf8(config.star(&dir, Some("work")).map(drop)));
+        config.star(tmp.path(), Some("work")).unwrap();
+        assert!(is_non_utf8(config.relocate_star("work", &dir)));
+        assert_eq!(Some(fs::canonicalize(tmp.path()).unwrap().as_path()), config.starred_path("work"));
+    }
 }
@@ -0,0 +1,142 @@
+//! Finding git repositories nested anywhere under a directory.
+
+use crate::Error;
+use crate::worktree::worktree_owner;
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Directory names that are skipped by default, since they hold build output or dependencies and
+/// can be enormous.
+pub const DEFAULT_SKIP_DIRS: [&str; 6] = [
+    "node_modules",
+    "target",
+    "__pycache__",
+    ".venv",
+    ".tox",
+    ".cache",
+];
+
+#[derive(Clone, Debug)]
+pub struct DiscoverOptions {
+    /// How many directories below the root to look. The root itself is depth 0.
+    ///
+    /// `None` means there is no limit.
+    pub max_depth: Option<usize>,
+    /// Follow symlinks to directories. Each directory is still only visited once.
+    pub follow_symlinks: bool,
+    /// Names of directories that are never entered.
+    pub skip_dirs: Vec<String>,
+    /// Keep looking inside a repo once one is found, e.g. for repos nested in another repo's
+    /// ignored directories.
+    pub descend_into_repos: bool,
+}
+
+impl Default for DiscoverOptions {
+    fn default() -> Self {
+        DiscoverOptions {
+            max_depth: Some(5),
+            follow_symlinks: false,
+            skip_dirs: DEFAULT_SKIP_DIRS.iter().map(|dir| dir.to_string()).collect(),
+            descend_into_repos: false,
+        }
+    }
+}
+
+/// Find every repository at or below `root`, in a stable (sorted) order.
+///
+/// Directories that can't be read are reported as `Error::Io` without stopping the search.
+pub fn discover_repos<P: AsRef<Path>>(
+    root: P,
+    options: &DiscoverOptions,
+) -> Vec<Result<PathBuf, Error>> {
+    let mut found = Vec::new();
+    let mut visited = HashSet::new();
+    let mut stack = vec![(root.as_ref().to_path_buf(), 0)];
+
+    while let Some((dir, depth)) = stack.pop() {
+        // guard against symlink cycles and directories reachable through several links
+        if options.follow_symlinks {
+            match fs::canonicalize(&dir) {
+                Ok(canonical) => if !visited.insert(canonical) {
+                    continue;
+                },
+                Err(err) => {
+                    found.push(Err(Error::Io(dir, err)));
+                    continue;
+                },
+            }
+        }
+
+        if is_repo(&dir) {
+            found.push(Ok(dir.clone()));
+            if !options.descend_into_repos {
+                continue;
+            }
+        }
+
+        if options.max_depth.map(|max| depth >= max).unwrap_or(false) {
+            continue;
+        }
+
+        let children = match child_dirs(&dir, options) {
+            Ok(children) => children,
+            Err(err) => {
+                found.push(Err(Error::Io(dir, err)));
+                continue;
+            },
+        };
+
+        // the stack pops from the end, so push in reverse to visit in sorted order
+        stack.extend(children.into_iter().rev().map(|child| (child, depth + 1)));
+    }
+
+    // a linked worktree is reported with the repo that owns it, so it is only listed on its own if
+    // that repo wasn't found
+    let owners: HashSet<_> = found.iter()
+        .filter_map(|repo| repo.as_ref().ok())
+        .filter_map(|repo| fs::canonicalize(repo).ok())
+        .collect();
+    found.retain(|repo| match repo {
+        Ok(dir) => worktree_owner(dir).is_none_or(|owner| !owners.contains(&owner)),
+        Err(_) => true,
+    });
+
+    found
+}
+
+/// True if `dir` is the working directory of a repo or is itself a bare repo.
+pub fn is_repo(dir: &Path) -> bool {
+    // `.git` is a file for linked worktrees and submodules
+    if dir.join(".git").exists() {
+        return true;
+    }
+
+    dir.join("HEAD").is_file() && dir.join("objects").is_dir() && dir.join("refs").is_dir()
+}
+
+fn child_dirs(dir: &Path, options: &DiscoverOptions) -> std::io::Result<Vec<PathBuf>> {
+    let mut children = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        if name == ".git" || options.skip_dirs.iter().any(|skip| name == skip.as_str()) {
+            continue;
+        }
+
+        let file_type = entry.file_type()?;
+        let is_dir = if file_type.is_symlink() {
+            // a dangling link is not worth reporting
+            options.follow_symlinks && entry.path().is_dir()
+        } else {
+            file_type.is_dir()
+        };
+        if is_dir {
+            children.push(entry.path());
+        }
+    }
+
+    children.sort();
+    Ok(children)
+}
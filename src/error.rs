@@ -0,0 +1,75 @@
+use crate::config::ConfigError;
+
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+/// Errors returned by the public functions of this crate.
+#[derive(Debug)]
+pub enum Error {
+    /// An error reported by libgit2.
+    Git(git2::Error),
+    /// No git repository was found at the path.
+    NotARepository(PathBuf),
+    /// A path could not be read while looking for repositories.
+    Io(PathBuf, io::Error),
+    /// Two commits were expected to share history but have no common ancestor.
+    ///
+    /// This happens with orphan branches (e.g. `gh-pages`) and with grafted or shallow clones.
+    UnrelatedHistories {
+        one: git2::Oid,
+        two: git2::Oid,
+    },
+    /// A branch, remote or alias name is not valid UTF-8.
+    ///
+    /// Contains the name with invalid sequences replaced, for display purposes only.
+    NonUtf8Name(String),
+    Config(ConfigError),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Git(err) => write!(f, "git error: {}", err),
+            Error::NotARepository(path) => write!(f, "not a git repo: {}", path.display()),
+            Error::Io(path, err) => write!(f, "failed to read {}: {}", path.display(), err),
+            Error::UnrelatedHistories { one, two } =>
+                write!(f, "commits {} and {} have no common ancestor", one, two),
+            Error::NonUtf8Name(name) => write!(f, "name is not valid UTF-8: {:?}", name),
+            Error::Config(err) => write!(f, "configuration error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Git(err) => Some(err),
+            Error::Config(err) => Some(err),
+            Error::Io(_, err) => Some(err),
+            Error::NotARepository(_)
+            | Error::UnrelatedHistories { .. }
+            | Error::NonUtf8Name(_) => None,
+        }
+    }
+}
+
+impl Error {
+    /// True if the error means a path couldn't be inspected at all, rather than that something
+    /// went wrong while inspecting a repo.
+    pub fn is_unreadable(&self) -> bool {
+        matches!(self, Error::NotARepository(_) | Error::Io(..))
+    }
+}
+
+impl From<git2::Error> for Error {
+    fn from(err: git2::Error) -> Error {
+        Error::Git(err)
+    }
+}
+
+impl From<ConfigError> for Error {
+    fn from(err: ConfigError) -> Error {
+        Error::Config(err)
+    }
+}
@@ -0,0 +1,168 @@
+//! Cheap detection of repos that changed since their status was last computed.
+//!
+//! Instead of reading any objects, this hashes the modification times of every file `get_status`
+//! depends on. If the hash is unchanged, a previously computed status is still correct.
+
+use crate::Error;
+
+use git2::Repository;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// Files in the git directory that affect the status, besides everything under `refs/`.
+const GIT_FILES: [&str; 6] = [
+    "index",
+    "HEAD",
+    "config",
+    "packed-refs",
+    "logs/refs/stash",
+    // rewritten by every fetch, even one that updates no refs, and read for the last fetch time
+    "FETCH_HEAD",
+];
+
+pub(crate) fn fingerprint(repo: &Repository) -> Result<u64, Error> {
+    let mut hasher = Fnv::new();
+
+    let git_dir = repo.path();
+    hash_git_dir(&mut hasher, git_dir);
+    // a linked worktree's git dir only has its own HEAD, index and the like, while its refs and
+    // config are in the git dir of the repo that owns it, where other worktrees update them
+    if repo.is_worktree() {
+        hash_git_dir(&mut hasher, &crate::common_dir(git_dir));
+    }
+    // whether the LFS hook is installed
+    if let Some(hooks) = crate::lfs::hooks_dir(repo, &crate::common_dir(git_dir)) {
+        hash_mtime(&mut hasher, &hooks.join("pre-push"));
+    }
+
+    if let Some(workdir) = repo.workdir() {
+        // modified and deleted tracked files
+        for entry in repo.index()?.iter() {
+            hash_mtime(&mut hasher, &workdir.join(entry_path(&entry.path)));
+        }
+
+        // new untracked files change the mtime of their directory, so files themselves are skipped
+        hash_tree(&mut hasher, workdir, &mut |dir| {
+            if dir.file_name().map(|name| name == ".git").unwrap_or(false) {
+                return false;
+            }
+
+            // ignored directories can't change the status and are often huge
+            let relative = dir.strip_prefix(workdir).unwrap_or(dir);
+            !repo.is_path_ignored(relative.join("")).unwrap_or(false)
+        });
+
+        // a submodule's git directory is usually inside ours, under modules/, but its refs and
+        // index aren't covered above
+        for submodule in repo.submodules()? {
+            if let Ok(sub) = submodule.open() {
+                hasher.write_u64(fingerprint(&sub)?);
+            }
+        }
+    }
+
+    // linked worktrees have their own HEAD, index and files, under worktrees/ and wherever they
+    // were checked out
+    hash_mtime(&mut hasher, &git_dir.join("worktrees"));
+    for worktree in crate::worktree::linked_worktrees(repo)? {
+        hash_mtime(&mut hasher, &worktree.git_dir.join("locked"));
+        match Repository::open(&worktree.path) {
+            Ok(worktree) => hasher.write_u64(fingerprint(&worktree)?),
+            Err(_) => hasher.write(b"unreadable"),
+        }
+    }
+
+    Ok(hasher.finish())
+}
+
+fn hash_git_dir(hasher: &mut Fnv, git_dir: &Path) {
+    // files like MERGE_HEAD or rebase-merge/ only show up as changes to the directory itself
+    hash_mtime(hasher, git_dir);
+    for name in &GIT_FILES {
+        hash_mtime(hasher, &git_dir.join(name));
+    }
+    // refs are updated by renaming a lock file over them, which also touches their directory
+    hash_tree(hasher, &git_dir.join("refs"), &mut |_| true);
+}
+
+/// Hash the mtime of `dir` and of every directory below it that `enter` accepts.
+fn hash_tree<F: FnMut(&Path) -> bool>(hasher: &mut Fnv, dir: &Path, enter: &mut F) {
+    let mut stack = vec![dir.to_path_buf()];
+    while let Some(dir) = stack.pop() {
+        hash_mtime(hasher, &dir);
+
+        // an unreadable directory is hashed by its mtime alone
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+
+        let mut children: Vec<_> = entries.flatten()
+            .filter(|entry| entry.file_type().map(|t| t.is_dir()).unwrap_or(false))
+            .map(|entry| entry.path())
+            .filter(|path| enter(path))
+            .collect();
+        // the hash depends on the order, and read_dir's order is unspecified
+        children.sort();
+        stack.extend(children);
+    }
+}
+
+fn hash_mtime(hasher: &mut Fnv, path: &Path) {
+    let name = path.as_os_str().as_encoded_bytes();
+    hasher.write_u64(name.len() as u64);
+    hasher.write(name);
+    match fs::symlink_metadata(path) {
+        Ok(meta) => {
+            let mtime = meta.modified()
+                .ok()
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .unwrap_or_default();
+            hasher.write_u64(mtime.as_secs());
+            hasher.write_u64(mtime.subsec_nanos().into());
+            hasher.write_u64(meta.len());
+        },
+        // a missing file is a state too, e.g. no packed-refs yet
+        Err(_) => hasher.write(b"missing"),
+    }
+}
+
+/// 64-bit FNV-1a. Fingerprints are saved in the index, so unlike with `DefaultHasher`, whose
+/// algorithm may change in any Rust release, the same files have to give the same hash.
+struct Fnv(u64);
+
+impl Fnv {
+    fn new() -> Fnv {
+        Fnv(0xcbf2_9ce4_8422_2325)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= u64::from(byte);
+            self.0 = self.0.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+
+    fn write_u64(&mut self, value: u64) {
+        self.write(&value.to_le_bytes());
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+#[cfg(unix)]
+fn entry_path(bytes: &[u8]) -> PathBuf {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    PathBuf::from(OsStr::from_bytes(bytes))
+}
+
+#[cfg(not(unix))]
+fn entry_path(bytes: &[u8]) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(bytes).into_owned())
+}
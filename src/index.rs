@@ -0,0 +1,300 @@
+//! A persistent record of the repos in the starred directories.
+//!
+//! Scanning every starred directory and reading every repo can take a while, so `vrh index` saves
+//! what it found and later commands can answer questions from the saved index instead.
+
+use crate::{get_status_with_options, open_repo, Error, RepoStatus, StatusOptions};
+use crate::batch::map_repos;
+use crate::config::ConfigError;
+use crate::discover::{discover_repos, DiscoverOptions};
+use crate::fingerprint::fingerprint;
+
+use git2::{BranchType, Repository};
+use serde::{Serialize, Deserialize};
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Incremented whenever a field of the saved index is added without a default, removed or
+/// changes meaning.
+pub const INDEX_VERSION: u32 = 1;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all="snake_case")]
+pub struct Index {
+    pub schema_version: u32,
+    /// Unix time of the last scan, or `None` if the index was never built.
+    pub updated: Option<i64>,
+    /// Every repo found, sorted by path.
+    pub repos: Vec<IndexedRepo>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all="snake_case")]
+pub struct IndexedRepo {
+    pub path: String,
+    /// Alias of the starred directory the repo was found in.
+    pub alias: Option<String>,
+    pub remotes: Vec<IndexedRemote>,
+    /// The branch the remote considers its default, or the current branch if that is unknown.
+    pub default_branch: Option<String>,
+    /// Unix time of the newest commit on any local branch.
+    pub last_commit: Option<i64>,
+    /// Unix time the repo was read.
+    pub indexed_at: i64,
+    /// Hash of the modification times the status depends on, see `cached_status`.
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub fingerprint: Option<u64>,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub status: Option<RepoStatus>,
+    /// Present only if the repo could not be read, with a human readable message.
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all="snake_case")]
+pub struct IndexedRemote {
+    pub name: String,
+    /// `None` if the URL is not valid UTF-8.
+    pub url: Option<String>,
+}
+
+impl Default for Index {
+    fn default() -> Self {
+        Index {
+            schema_version: INDEX_VERSION,
+            updated: None,
+            repos: Vec::new(),
+        }
+    }
+}
+
+impl Index {
+    /// Read a saved index. A missing file, or one saved with another `INDEX_VERSION`, is treated
+    /// as an empty index.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Index, Error> {
+        let path = path.as_ref();
+        let file = match fs::File::open(path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Index::default()),
+            Err(err) => return Err(Error::Io(path.to_path_buf(), err)),
+        };
+
+        let index: serde_json::Value = serde_json::from_reader(BufReader::new(file))
+            .map_err(ConfigError::Parsing)?;
+        if index["schema_version"] != INDEX_VERSION {
+            return Ok(Index::default());
+        }
+        serde_json::from_value(index).map_err(|err| ConfigError::Parsing(err).into())
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let path = path.as_ref();
+        let io_err = |err| Error::Io(path.to_path_buf(), err);
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(io_err)?;
+        }
+
+        // write next to the index and move it into place, so a failed write can't lose the index
+        let tmp = path.with_extension("tmp");
+        let writer = BufWriter::new(fs::File::create(&tmp).map_err(io_err)?);
+        serde_json::to_writer_pretty(writer, self)
+            .map_err(ConfigError::Parsing)?;
+        fs::rename(&tmp, path).map_err(io_err)
+    }
+
+    /// Replace the record of a repo that is already in the index, keeping its alias.
+    ///
+    /// Returns true if the record changed. Records read with options that aren't `is_cacheable`
+    /// must not be used.
+    pub fn update(&mut self, mut repo: IndexedRepo) -> bool {
+        match self.repos.binary_search_by(|indexed| indexed.path.cmp(&repo.path)) {
+            Ok(i) => {
+                repo.alias = self.repos[i].alias.take();
+                let changed = self.repos[i] != repo;
+                self.repos[i] = repo;
+                changed
+            },
+            Err(_) => false,
+        }
+    }
+
+    /// Find the record for the repo at `path`, as it was found while scanning.
+    pub fn get<P: AsRef<Path>>(&self, path: P) -> Option<&IndexedRepo> {
+        let path = path.as_ref().to_string_lossy();
+        self.repos.binary_search_by(|repo| repo.path.as_str().cmp(&path))
+            .ok()
+            .map(|i| &self.repos[i])
+    }
+}
+
+/// Build a new index of every repo below the starred directories, given as `(alias, dir)`.
+///
+/// Records in `previous` are reused for repos that haven't changed since; pass `None` to read every
+/// repo again. A repo that can't be read is still indexed, with its error. Problems finding repos
+/// in the first place are returned separately.
+pub fn scan(
+    starred: &[(&str, &Path)],
+    options: &DiscoverOptions,
+    workers: usize,
+    previous: Option<&Index>,
+) -> (Index, Vec<Error>) {
+    let mut aliases = HashMap::new();
+    let mut paths = Vec::new();
+    let mut errors = Vec::new();
+    for (alias, dir) in starred {
+        for found in discover_repos(dir, options) {
+            match found {
+                Ok(path) => {
+                    // starred directories may be nested, so a repo can be found more than once
+                    if !aliases.contains_key(&path) {
+                        aliases.insert(path.clone(), alias.to_string());
+                        paths.push(path);
+                    }
+                },
+                Err(err) => errors.push(err),
+            }
+        }
+    }
+
+    let previous: HashMap<_, _> = previous.iter()
+        .flat_map(|index| &index.repos)
+        .filter(|repo| aliases.contains_key(Path::new(&repo.path)))
+        .map(|repo| (PathBuf::from(&repo.path), repo.clone()))
+        .collect();
+    let aliases = Arc::new(aliases);
+    let previous = Arc::new(previous);
+    let mut repos: Vec<_> = map_repos(paths, workers, move |path| {
+        index_repo(path, aliases.get(path).map(String::as_str), previous.get(path))
+    })
+        .map(|(_, repo)| repo)
+        .collect();
+    repos.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let index = Index {
+        schema_version: INDEX_VERSION,
+        updated: Some(unix_now()),
+        repos,
+    };
+
+    (index, errors)
+}
+
+/// Read everything the index records about the repo at `path`.
+///
+/// A repo that can't be read is recorded with its error.
+pub fn index_repo(path: &Path, alias: Option<&str>, previous: Option<&IndexedRepo>) -> IndexedRepo {
+    read_repo(path, alias, &StatusOptions::default(), previous)
+        .unwrap_or_else(|err| IndexedRepo {
+            path: path.to_string_lossy().into_owned(),
+            alias: alias.map(str::to_string),
+            remotes: Vec::new(),
+            default_branch: None,
+            last_commit: None,
+            indexed_at: unix_now(),
+            fingerprint: None,
+            status: None,
+            error: Some(err.to_string()),
+        })
+}
+
+/// Read the repo at `path`, or reuse `previous` if nothing it depends on changed since.
+///
+/// Statuses read with options the index doesn't store are never reused, see `is_cacheable`. The
+/// returned record always has a status.
+pub fn read_repo(
+    path: &Path,
+    alias: Option<&str>,
+    options: &StatusOptions,
+    previous: Option<&IndexedRepo>,
+) -> Result<IndexedRepo, Error> {
+    let mut repo = open_repo(path)?;
+
+    // taken before reading anything, so changes made while reading are picked up next time
+    let fingerprint = fingerprint(&repo)?;
+    if let Some(previous) = previous {
+        let unchanged = previous.fingerprint == Some(fingerprint) && previous.status.is_some();
+        if unchanged && is_cacheable(options) {
+            return Ok(IndexedRepo {
+                alias: alias.map(str::to_string),
+                ..previous.clone()
+            });
+        }
+    }
+
+    let mut remotes = Vec::new();
+    let names = repo.remotes()?;
+    for name in names.iter().flatten() {
+        let remote = repo.find_remote(name)?;
+        remotes.push(IndexedRemote {
+            name: name.to_string(),
+            url: remote.url().map(str::to_string),
+        });
+    }
+
+    Ok(IndexedRepo {
+        path: path.to_string_lossy().into_owned(),
+        alias: alias.map(str::to_string),
+        default_branch: default_branch(&repo, &remotes),
+        remotes,
+        last_commit: last_commit(&repo)?,
+        indexed_at: unix_now(),
+        fingerprint: Some(fingerprint),
+        status: Some(get_status_with_options(&mut repo, options)?),
+        error: None,
+    })
+}
+
+/// True if statuses read with `options` can be stored in and reused from the index.
+///
+/// Changed paths would make the index huge, verifying remotes depends on more than the repo, and
+/// reflogs are not part of the fingerprint.
+pub fn is_cacheable(options: &StatusOptions) -> bool {
+    !options.include_paths && !options.verify_remotes && !options.deep
+}
+
+fn default_branch(repo: &Repository, remotes: &[IndexedRemote]) -> Option<String> {
+    // clones record the remote's default branch as <remote>/HEAD
+    for remote in remotes {
+        let head = match repo.find_reference(&format!("refs/remotes/{}/HEAD", remote.name)) {
+            Ok(head) => head,
+            Err(_) => continue,
+        };
+        let prefix = format!("refs/remotes/{}/", remote.name);
+        if let Some(branch) = head.symbolic_target().and_then(|t| t.strip_prefix(&prefix)) {
+            return Some(branch.to_string());
+        }
+    }
+
+    // HEAD names a branch even before its first commit
+    let head = repo.find_reference("HEAD").ok()?;
+    head.symbolic_target()?
+        .strip_prefix("refs/heads/")
+        .map(str::to_string)
+}
+
+fn last_commit(repo: &Repository) -> Result<Option<i64>, Error> {
+    let mut last = None;
+    for branch in repo.branches(Some(BranchType::Local))? {
+        let (branch, _) = branch?;
+        if let Ok(commit) = branch.get().peel_to_commit() {
+            last = last.max(Some(commit.time().seconds()));
+        }
+    }
+
+    Ok(last)
+}
+
+fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs() as i64)
+        .unwrap_or(0)
+}
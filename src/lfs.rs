@@ -0,0 +1,175 @@
+//! Finding Git LFS objects that may only be stored on this device.
+//!
+//! LFS keeps large files out of the repo, committing small pointer files instead and uploading
+//! the contents from its pre-push hook. Nothing records which objects were uploaded, so an object
+//! is assumed to be on a remote once a commit that refers to it is in a remote branch, as long as
+//! the hook is installed. Without it, only the objects of the checked out files are checked
+//! besides those of unpushed commits.
+
+use crate::{Error, LfsObject, LfsStatus};
+
+use git2::{Delta, ObjectType, Oid, Repository, Sort, TreeWalkMode, TreeWalkResult};
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const POINTER_VERSION: &str = "version https://git-lfs.github.com/spec/v1\n";
+/// Pointer files are always smaller than this, so bigger blobs aren't read.
+const MAX_POINTER_SIZE: usize = 1024;
+
+/// LFS status of `repo`, or `None` if it doesn't use LFS.
+pub(crate) fn lfs_status(
+    repo: &Repository,
+    remote_tips: &[Oid],
+) -> Result<Option<LfsStatus>, Error> {
+    let common_dir = crate::common_dir(repo.path());
+    let objects_dir = common_dir.join("lfs").join("objects");
+    if !tracks_lfs_files(repo)? && !objects_dir.is_dir() {
+        return Ok(None);
+    }
+
+    let pre_push_hook = hooks_dir(repo, &common_dir)
+        .and_then(|hooks| fs::read_to_string(hooks.join("pre-push")).ok())
+        .is_some_and(|hook| hook.contains("git lfs") || hook.contains("git-lfs"));
+
+    let mut seen = HashSet::new();
+    let mut unpushed_objects = Vec::new();
+    let mut record = |id: Oid, path: String| -> Result<(), Error> {
+        if !seen.insert(id) {
+            return Ok(());
+        }
+        let (oid, size) = match read_pointer(repo, id)? {
+            Some(pointer) => pointer,
+            None => return Ok(()),
+        };
+        // an object that isn't stored here was never downloaded, so there is nothing to lose
+        if objects_dir.join(&oid[..2]).join(&oid[2..4]).join(&oid).is_file() {
+            unpushed_objects.push(LfsObject { oid, size, path });
+        }
+        Ok(())
+    };
+
+    // without the hook pushing a branch doesn't upload its objects, so the checked out files may
+    // not be on a remote either; older pushed commits aren't searched, which would mean reading
+    // the whole history
+    if !pre_push_hook {
+        let tree = match repo.head() {
+            Ok(head) => Some(head.peel_to_tree()?),
+            // nothing committed yet
+            Err(_) => None,
+        };
+        let mut blobs = Vec::new();
+        if let Some(tree) = tree {
+            tree.walk(TreeWalkMode::PreOrder, |dir, entry| {
+                if entry.kind() == Some(ObjectType::Blob) {
+                    let name = String::from_utf8_lossy(entry.name_bytes());
+                    blobs.push((entry.id(), format!("{}{}", dir, name)));
+                }
+                TreeWalkResult::Ok
+            })?;
+        }
+        for (id, path) in blobs {
+            record(id, path)?;
+        }
+    }
+
+    let mut walk = repo.revwalk()?;
+    walk.set_sorting(Sort::TOPOLOGICAL);
+    walk.push_glob("refs/heads/*")?;
+    if repo.head_detached()? {
+        walk.push_head()?;
+    }
+    for &tip in remote_tips {
+        walk.hide(tip)?;
+    }
+    for id in walk {
+        let commit = repo.find_commit(id?)?;
+        let parent_tree = match commit.parent_count() {
+            0 => None,
+            _ => Some(commit.parent(0)?.tree()?),
+        };
+        let diff = repo.diff_tree_to_tree(parent_tree.as_ref(), Some(&commit.tree()?), None)?;
+        for delta in diff.deltas() {
+            if !matches!(delta.status(), Delta::Added | Delta::Modified | Delta::Renamed | Delta::Copied) {
+                continue;
+            }
+            let file = delta.new_file();
+            let path = file.path()
+                .map(|path| path.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            record(file.id(), path)?;
+        }
+    }
+
+    unpushed_objects.sort_by(|one, two| one.path.cmp(&two.path));
+    Ok(Some(LfsStatus {
+        pre_push_hook,
+        unpushed_objects,
+    }))
+}
+
+/// True if the `.gitattributes` at the top of the working tree or of HEAD send files through the
+/// LFS filter.
+fn tracks_lfs_files(repo: &Repository) -> Result<bool, Error> {
+    let uses_lfs = |attributes: &[u8]| String::from_utf8_lossy(attributes).contains("filter=lfs");
+
+    if let Some(workdir) = repo.workdir() {
+        if fs::read(workdir.join(".gitattributes")).is_ok_and(|attributes| uses_lfs(&attributes)) {
+            return Ok(true);
+        }
+    }
+
+    let tree = match repo.head() {
+        Ok(head) => head.peel_to_tree()?,
+        // nothing committed yet
+        Err(_) => return Ok(false),
+    };
+    let entry = match tree.get_name(".gitattributes") {
+        Some(entry) => entry,
+        None => return Ok(false),
+    };
+    Ok(repo.find_blob(entry.id()).is_ok_and(|blob| uses_lfs(blob.content())))
+}
+
+/// The LFS object id and size of the pointer file in blob `id`, or `None` if it isn't one.
+fn read_pointer(repo: &Repository, id: Oid) -> Result<Option<(String, u64)>, Error> {
+    let (size, kind) = repo.odb()?.read_header(id)?;
+    if kind != ObjectType::Blob || size >= MAX_POINTER_SIZE {
+        return Ok(None);
+    }
+
+    let blob = repo.find_blob(id)?;
+    let contents = match std::str::from_utf8(blob.content()) {
+        Ok(contents) => contents,
+        Err(_) => return Ok(None),
+    };
+    let fields = match contents.strip_prefix(POINTER_VERSION) {
+        Some(fields) => fields,
+        None => return Ok(None),
+    };
+
+    let mut oid = None;
+    let mut size = None;
+    for line in fields.lines() {
+        if let Some(value) = line.strip_prefix("oid sha256:") {
+            oid = Some(value);
+        } else if let Some(value) = line.strip_prefix("size ") {
+            size = value.parse().ok();
+        }
+    }
+    Ok(match (oid, size) {
+        (Some(oid), Some(size)) if oid.len() == 64 && oid.bytes().all(|b| b.is_ascii_hexdigit()) =>
+            Some((oid.to_string(), size)),
+        _ => None,
+    })
+}
+
+/// Directory git runs hooks from, honoring `core.hooksPath`.
+pub(crate) fn hooks_dir(repo: &Repository, common_dir: &Path) -> Option<PathBuf> {
+    match repo.config().ok()?.get_path("core.hooksPath") {
+        // a relative path is relative to where hooks run, the top of the working tree
+        Ok(path) => Some(repo.workdir().unwrap_or(common_dir).join(path)),
+        Err(_) => Some(common_dir.join("hooks")),
+    }
+}
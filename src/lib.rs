@@ -1,29 +1,635 @@
-use git2::{Repository, RepositoryState};
+use git2::Repository;
 
 use serde::{Serialize, Deserialize};
 
-use std::collections::HashMap;
-use std::path::Path;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
 
+pub mod backup;
+pub mod batch;
 pub mod config;
+pub mod discover;
+pub mod index;
+mod error;
+mod fingerprint;
+mod lfs;
+mod operation;
+mod patch_id;
+mod reflog;
+mod submodule;
+mod verify;
+pub mod report;
+pub mod stash;
+mod tags;
+mod worktree;
+
+pub use error::Error;
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all="snake_case")]
 pub struct RepoStatus {
     pub bare: bool,
-    /// True if nothing is staged and there are no untracked files.
-    pub clean_status: bool,
-    /// True if there is no conflict resolution in progress.
-    pub clean_state: bool,
-    pub stashes: usize,
+    pub head: Head,
+    /// Breakdown of files that are staged, modified, untracked or conflicted.
+    pub files: FileStatus,
+    /// A merge, rebase or similar that was started but not finished, if any.
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub operation: Option<Operation>,
+    /// Stashes, newest first. They only exist on this device.
+    pub stashes: Vec<Stash>,
+    /// Tags that may not be on any remote, sorted by name.
+    #[serde(default, skip_serializing_if="Vec::is_empty")]
+    pub unpushed_tags: Vec<UnpushedTag>,
+    /// Commits that only reflogs still refer to, newest first. `None` unless
+    /// `StatusOptions::deep` was set.
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub reflog_commits: Option<Vec<ReflogCommit>>,
     pub remotes: Vec<Remote>,
     pub branches: HashMap<String, BranchStatus>,
+    /// Every submodule of the working tree, sorted by path.
+    #[serde(default, skip_serializing_if="Vec::is_empty")]
+    pub submodules: Vec<SubmoduleStatus>,
+    /// Working trees added with `git worktree add`, sorted by name. Only listed for the repo that
+    /// owns them, not when the status of a linked worktree itself is collected.
+    #[serde(default, skip_serializing_if="Vec::is_empty")]
+    pub worktrees: Vec<WorktreeStatus>,
+    /// Large files stored with Git LFS, or `None` if the repo doesn't use it.
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub lfs: Option<LfsStatus>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all="snake_case")]
+pub struct Stash {
+    /// Position in the stash list, as in `stash@{index}`.
+    pub index: usize,
+    /// Id of the stash commit.
+    pub id: String,
+    pub message: String,
+    /// Branch that was checked out when the stash was made, or `None` if HEAD was detached.
+    pub branch: Option<String>,
+    /// Unix time the stash was made.
+    pub time: i64,
+    /// Number of files the stash saves changes to, including untracked files.
+    pub files: usize,
+}
+
+/// A tag whose commit isn't in any remote branch, or that the remote-tracking refs of a remote
+/// that fetches tags show is missing or different there.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all="snake_case")]
+pub struct UnpushedTag {
+    pub name: String,
+    /// Id of the tag object for an annotated tag, or of the commit for a lightweight one.
+    pub id: String,
+    pub annotated: bool,
+    /// True if the tagged commit is contained in a remote branch.
+    pub merged_in_remote: bool,
+    /// Remotes whose tags are tracked but that have no tag of this name.
+    #[serde(default, skip_serializing_if="Vec::is_empty")]
+    pub missing_on: Vec<String>,
+    /// Remotes whose tags are tracked and that have a different tag of this name.
+    #[serde(default, skip_serializing_if="Vec::is_empty")]
+    pub differs_on: Vec<String>,
+}
+
+/// A commit that is not reachable from any branch, tag or remote branch, but is still kept because
+/// a reflog refers to it, e.g. the old version of an amended commit or the tip of a deleted branch.
+///
+/// Such commits are deleted by `git gc` once their reflog entries expire.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all="snake_case")]
+pub struct ReflogCommit {
+    pub id: String,
+    /// First line of the commit message.
+    pub summary: String,
+    /// Unix time of the commit.
+    pub time: i64,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all="snake_case")]
+pub struct SubmoduleStatus {
+    pub name: String,
+    /// Path of the submodule in the superproject's working tree.
+    pub path: String,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub url: Option<String>,
+    /// Commit the superproject's HEAD records for the submodule, or `None` if the submodule was
+    /// added but not committed yet.
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub recorded: Option<String>,
+    /// True if the recorded commit is contained in a remote branch of the submodule.
+    ///
+    /// `None` if that can't be told, because the submodule isn't checked out or doesn't have the
+    /// commit.
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub recorded_in_remote: Option<bool>,
+    /// The submodule's own status, or `None` if it isn't checked out or couldn't be read.
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub status: Option<Box<RepoStatus>>,
+    /// Present only if the submodule is checked out but couldn't be read, with a human readable
+    /// message.
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all="snake_case")]
+pub struct LfsStatus {
+    /// True if the pre-push hook that uploads LFS objects is installed. Without it, pushing a
+    /// branch leaves its LFS objects behind.
+    pub pre_push_hook: bool,
+    /// Objects stored locally that no remote is known to have, sorted by path.
+    ///
+    /// That is every object a local commit refers to that isn't in a remote branch, and if the hook
+    /// isn't installed, every object the files checked out at HEAD refer to. Older pushed commits
+    /// aren't searched, reading every commit of large histories would take too long, but
+    /// `BackupProblem::MissingLfsHook` is reported for them.
+    #[serde(default, skip_serializing_if="Vec::is_empty")]
+    pub unpushed_objects: Vec<LfsObject>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all="snake_case")]
+pub struct LfsObject {
+    /// SHA-256 of the contents, as in the pointer file.
+    pub oid: String,
+    /// Size of the contents in bytes.
+    pub size: u64,
+    /// Path of the pointer file in the first commit found that adds it.
+    pub path: String,
+}
+
+/// A linked worktree, which has its own HEAD, index and files but shares the branches, tags and
+/// stashes reported for the repo.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all="snake_case")]
+pub struct WorktreeStatus {
+    /// Name of the worktree's git dir under `.git/worktrees`.
+    pub name: String,
+    /// Path of the worktree's working directory.
+    pub path: String,
+    /// True if `git worktree lock` keeps it from being pruned, e.g. because it is on a removable
+    /// disk.
+    #[serde(default, skip_serializing_if="std::ops::Not::not")]
+    pub locked: bool,
+    /// True if the working directory no longer exists.
+    #[serde(default, skip_serializing_if="std::ops::Not::not")]
+    pub missing: bool,
+    /// What the worktree has checked out, or `None` if it is missing or couldn't be read.
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub head: Option<Head>,
+    #[serde(default)]
+    pub files: FileStatus,
+    /// A merge, rebase or similar that was started in the worktree but not finished, if any.
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub operation: Option<Operation>,
+    /// Present only if the worktree exists but couldn't be read, with a human readable message.
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// What HEAD points to.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag="kind", rename_all="snake_case")]
+pub enum Head {
+    /// A local branch is checked out.
+    Branch {
+        name: String,
+    },
+    /// The checked out branch has no commits yet, as in a freshly initialized repo.
+    Unborn {
+        name: String,
+    },
+    /// A commit is checked out directly. Commits made from here are on no branch.
+    Detached {
+        commit: String,
+        /// True if the commit is contained in a remote branch.
+        merged_in_remote: bool,
+    },
+}
+
+/// A git command that stopped part way through, e.g. a rebase waiting for conflicts to be
+/// resolved.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all="snake_case")]
+pub struct Operation {
+    pub kind: OperationKind,
+    /// Branch the operation updates once it is finished, or `None` if HEAD was detached.
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub branch: Option<String>,
+    /// Id of the commit a rebase is replaying the branch onto.
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub onto: Option<String>,
+    /// How many of its commits or patches a rebase or `git am` has got to.
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub progress: Option<Progress>,
+    /// Paths with unresolved conflicts, sorted.
+    #[serde(default, skip_serializing_if="Vec::is_empty")]
+    pub conflicted: Vec<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all="snake_case")]
+pub enum OperationKind {
+    Merge,
+    Revert,
+    CherryPick,
+    Bisect,
+    Rebase,
+    /// Since git 2.26 this includes rebases that weren't started with `--interactive` but use the
+    /// merge backend, the default, since they run the same way.
+    InteractiveRebase,
+    /// `git am`, applying patches from a mailbox.
+    ApplyMailbox,
+}
+
+impl OperationKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            OperationKind::Merge => "merge",
+            OperationKind::Revert => "revert",
+            OperationKind::CherryPick => "cherry-pick",
+            OperationKind::Bisect => "bisect",
+            OperationKind::Rebase => "rebase",
+            OperationKind::InteractiveRebase => "interactive rebase",
+            OperationKind::ApplyMailbox => "am",
+        }
+    }
+}
+
+/// Position in a sequence of steps, where `step` counts from 1.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all="snake_case")]
+pub struct Progress {
+    pub step: usize,
+    pub total: usize,
+}
+
+/// Options controlling how much work `get_status_with_options` does.
+#[derive(Clone, Debug, Default)]
+pub struct StatusOptions {
+    /// Record the path of every changed file in `FileStatus::paths`, not just the counts.
+    pub include_paths: bool,
+    /// Connect to every remote to check the local copies of its refs are up to date.
+    ///
+    /// Remotes that can't be reached are reported in `Remote::verification`, not as an error.
+    pub verify_remotes: bool,
+    /// Walk the reflogs of HEAD and the local branches for commits that nothing else refers to,
+    /// see `RepoStatus::reflog_commits`.
+    pub deep: bool,
+}
+
+/// Counts of files in the working tree and index that differ from HEAD.
+///
+/// A single file may be counted in more than one category, e.g. a file that was staged and then
+/// modified again counts as both `staged` and `modified`.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all="snake_case")]
+pub struct FileStatus {
+    /// New, modified or type changed files in the index.
+    pub staged: usize,
+    /// Modified or type changed files in the working tree that are not staged.
+    pub modified: usize,
+    /// Files deleted in the index or the working tree.
+    pub deleted: usize,
+    /// Files renamed in the index or the working tree.
+    pub renamed: usize,
+    /// Files that are neither tracked nor ignored.
+    pub untracked: usize,
+    /// Files with unresolved merge conflicts.
+    pub conflicted: usize,
+    /// Every changed file, only populated when `StatusOptions::include_paths` is set.
+    #[serde(skip_serializing_if="Vec::is_empty")]
+    pub paths: Vec<ChangedPath>,
+}
+
+impl FileStatus {
+    /// True if nothing is staged, modified, untracked or conflicted.
+    pub fn is_clean(&self) -> bool {
+        self.changes().next().is_none()
+    }
+
+    /// True if there are no changes other than the `allowed` kinds.
+    pub fn is_clean_except(&self, allowed: &[FileChange]) -> bool {
+        self.changes().all(|(change, _)| allowed.contains(&change))
+    }
+
+    pub fn count(&self, change: FileChange) -> usize {
+        match change {
+            FileChange::Staged => self.staged,
+            FileChange::Modified => self.modified,
+            FileChange::Deleted => self.deleted,
+            FileChange::Renamed => self.renamed,
+            FileChange::Untracked => self.untracked,
+            FileChange::Conflicted => self.conflicted,
+        }
+    }
+
+    /// Iterate over the kinds of change present, with the number of files for each.
+    pub fn changes(&self) -> impl Iterator<Item=(FileChange, usize)> + '_ {
+        FileChange::ALL.iter()
+            .map(move |&change| (change, self.count(change)))
+            .filter(|&(_, count)| count > 0)
+    }
+
+    fn record(&mut self, change: FileChange, path: Option<&str>) {
+        match change {
+            FileChange::Staged => self.staged += 1,
+            FileChange::Modified => self.modified += 1,
+            FileChange::Deleted => self.deleted += 1,
+            FileChange::Renamed => self.renamed += 1,
+            FileChange::Untracked => self.untracked += 1,
+            FileChange::Conflicted => self.conflicted += 1,
+        }
+
+        if let Some(path) = path {
+            self.paths.push(ChangedPath {
+                path: path.to_string(),
+                change,
+            });
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all="snake_case")]
+pub struct ChangedPath {
+    pub path: String,
+    pub change: FileChange,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all="snake_case")]
+pub enum FileChange {
+    Staged,
+    Modified,
+    Deleted,
+    Renamed,
+    Untracked,
+    Conflicted,
+}
+
+impl FileChange {
+    pub const ALL: [FileChange; 6] = [
+        FileChange::Staged,
+        FileChange::Modified,
+        FileChange::Deleted,
+        FileChange::Renamed,
+        FileChange::Untracked,
+        FileChange::Conflicted,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            FileChange::Staged => "staged",
+            FileChange::Modified => "modified",
+            FileChange::Deleted => "deleted",
+            FileChange::Renamed => "renamed",
+            FileChange::Untracked => "untracked",
+            FileChange::Conflicted => "conflicted",
+        }
+    }
+}
+
+impl std::str::FromStr for FileChange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<FileChange, String> {
+        FileChange::ALL.iter()
+            .find(|change| change.as_str() == s)
+            .copied()
+            .ok_or_else(|| format!("unknown kind of file change: {}", s))
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all="snake_case")]
 pub struct Remote {
-    name: String,
+    pub name: String,
+    /// URL fetched from. `None` if it isn't set or isn't valid UTF-8.
+    pub url: Option<String>,
+    /// URL pushed to, if it is configured separately from `url`.
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub push_url: Option<String>,
+    pub fetch_refspecs: Vec<String>,
+    #[serde(default, skip_serializing_if="Vec::is_empty")]
+    pub push_refspecs: Vec<String>,
+    /// Where pushed work ends up, judged from the push URL.
+    pub location: RemoteLocation,
+    /// Whether pushed work would survive losing the disk the repo is on.
+    pub distance: RemoteDistance,
+    /// Unix time the remote was last fetched from, if it ever was.
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub last_fetch: Option<i64>,
+    /// The result of comparing the remote's refs with the local copies, if it was asked for with
+    /// `StatusOptions::verify_remotes`.
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub verification: Option<RemoteVerification>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all="snake_case")]
+pub struct RemoteVerification {
+    /// Local refs fetched from the remote that no longer match it.
+    #[serde(default, skip_serializing_if="Vec::is_empty")]
+    pub stale_refs: Vec<StaleRef>,
+    /// Present if the remote couldn't be reached, in which case nothing was verified.
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// A remote-tracking ref (or mirrored ref) that differs from the ref it was fetched from.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all="snake_case")]
+pub struct StaleRef {
+    /// Full name of the local ref, e.g. `refs/remotes/origin/master`.
+    pub local_ref: String,
+    /// Commit the local ref points at, or `None` if it was never fetched.
+    pub recorded: Option<String>,
+    /// Commit the remote has, or `None` if it was deleted from the remote.
+    pub actual: Option<String>,
+}
+
+impl Remote {
+    /// Describe `remote` of the repo whose working directory (or git directory if bare) is `dir`.
+    fn new(name: String, remote: &git2::Remote, dir: &Path) -> Remote {
+        let mut out = Remote {
+            name,
+            url: remote.url().map(str::to_string),
+            push_url: remote.pushurl().map(str::to_string),
+            fetch_refspecs: refspec_strings(remote, git2::Direction::Fetch),
+            push_refspecs: refspec_strings(remote, git2::Direction::Push),
+            location: RemoteLocation::Unknown,
+            distance: RemoteDistance::Unknown,
+            last_fetch: None,
+            verification: None,
+        };
+        if let Some(url) = out.effective_push_url() {
+            let location = RemoteLocation::of_url(url);
+            out.distance = RemoteDistance::of_url(url, &location, dir);
+            out.location = location;
+        }
+        out
+    }
+
+    /// The URL pushes go to.
+    pub fn effective_push_url(&self) -> Option<&str> {
+        self.push_url.as_deref().or(self.url.as_deref())
+    }
+}
+
+fn refspec_strings(remote: &git2::Remote, direction: git2::Direction) -> Vec<String> {
+    // Direction doesn't implement PartialEq
+    remote.refspecs()
+        .filter(|spec| matches!(
+            (spec.direction(), direction),
+            (git2::Direction::Fetch, git2::Direction::Fetch)
+                | (git2::Direction::Push, git2::Direction::Push)
+        ))
+        .filter_map(|spec| spec.str().map(str::to_string))
+        .collect()
+}
+
+/// How far away a remote is from the repo, which decides what it can be a backup against.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all="snake_case")]
+pub enum RemoteDistance {
+    /// A path on the same filesystem as the repo, so both are lost with the disk.
+    SameFilesystem,
+    /// A path on another mounted filesystem, e.g. an external drive or a network share. Another
+    /// partition or btrfs subvolume of the same disk is also another filesystem, so the remote may
+    /// still be lost with the disk.
+    OtherFilesystem,
+    /// Another host.
+    Network,
+    /// A local path that doesn't exist, or a URL that couldn't be classified.
+    Unknown,
+}
+
+impl RemoteDistance {
+    fn of_url(url: &str, location: &RemoteLocation, dir: &Path) -> RemoteDistance {
+        match location {
+            RemoteLocation::Forge { .. } | RemoteLocation::Network { .. } => RemoteDistance::Network,
+            RemoteLocation::Unknown => RemoteDistance::Unknown,
+            RemoteLocation::Local => {
+                // git resolves relative paths from the working directory
+                let path = dir.join(url.strip_prefix("file://").unwrap_or(url));
+                match same_filesystem(&path, dir) {
+                    Some(true) => RemoteDistance::SameFilesystem,
+                    Some(false) => RemoteDistance::OtherFilesystem,
+                    None => RemoteDistance::Unknown,
+                }
+            },
+        }
+    }
+
+    /// True if the remote is on another filesystem or host.
+    ///
+    /// Filesystems are all that can be told apart, so a remote on another partition of the disk
+    /// the repo is on counts as off device too.
+    pub fn is_off_device(self) -> bool {
+        matches!(self, RemoteDistance::OtherFilesystem | RemoteDistance::Network)
+    }
+}
+
+/// Whether two existing paths are on the same filesystem, or `None` if either can't be read.
+#[cfg(unix)]
+fn same_filesystem(one: &Path, two: &Path) -> Option<bool> {
+    use std::os::unix::fs::MetadataExt;
+
+    Some(one.metadata().ok()?.dev() == two.metadata().ok()?.dev())
+}
+
+#[cfg(not(unix))]
+fn same_filesystem(one: &Path, two: &Path) -> Option<bool> {
+    // without device ids, compare drives instead
+    let one = one.canonicalize().ok()?;
+    let two = two.canonicalize().ok()?;
+    Some(one.components().next() == two.components().next())
+}
+
+/// Hosts of well known git hosting services.
+pub const KNOWN_FORGES: [&str; 8] = [
+    "github.com",
+    "gitlab.com",
+    "bitbucket.org",
+    "codeberg.org",
+    "git.sr.ht",
+    "gitea.com",
+    "ssh.dev.azure.com",
+    "dev.azure.com",
+];
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag="kind", rename_all="snake_case")]
+pub enum RemoteLocation {
+    /// A path on this machine, including `file://` URLs.
+    Local,
+    /// One of the `KNOWN_FORGES`.
+    Forge {
+        host: String,
+    },
+    /// Any other host.
+    Network {
+        host: String,
+    },
+    /// The URL is missing, or uses a remote helper (`<transport>::<address>`).
+    Unknown,
+}
+
+impl RemoteLocation {
+    /// Work out where a URL points, using the URL syntaxes git accepts.
+    pub fn of_url(url: &str) -> RemoteLocation {
+        if url.starts_with("file://") {
+            return RemoteLocation::Local;
+        }
+
+        if let Some(start) = url.find("://") {
+            let authority = url[start + 3..].split('/').next().unwrap_or_default();
+            return RemoteLocation::of_host(authority);
+        }
+
+        // scp-like syntax, `[user@]host:path`, is only used if there is no slash before the colon
+        if let Some(colon) = url.find(':') {
+            let host = &url[..colon];
+            let drive_letter = host.len() == 1 && host.chars().all(|c| c.is_ascii_alphabetic());
+            if url[colon + 1..].starts_with(':') {
+                return RemoteLocation::Unknown;
+            }
+            if !host.contains('/') && !drive_letter {
+                return RemoteLocation::of_host(host);
+            }
+        }
+
+        RemoteLocation::Local
+    }
+
+    /// Classify the authority part of a URL, `[user@]host[:port]`.
+    fn of_host(authority: &str) -> RemoteLocation {
+        let host = authority.rsplit('@').next().unwrap_or_default();
+        let host = if host.starts_with('[') {
+            // an IPv6 address
+            host.split(']').next().map(|h| &h[1..]).unwrap_or_default()
+        } else {
+            host.split(':').next().unwrap_or_default()
+        };
+        let host = host.to_ascii_lowercase();
+
+        if host.is_empty() {
+            RemoteLocation::Unknown
+        } else if KNOWN_FORGES.contains(&host.as_str()) {
+            RemoteLocation::Forge { host }
+        } else {
+            RemoteLocation::Network { host }
+        }
+    }
+
+    pub fn is_local(&self) -> bool {
+        matches!(self, RemoteLocation::Local)
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
@@ -32,6 +638,18 @@ pub enum BranchStatus {
     LocalBranch {
         merged_in_remote: bool,
     },
+    /// A local branch that isn't merged in any remote branch, but whose changes were applied to a
+    /// remote branch as different commits. It can be deleted without losing work.
+    EquivalentInRemote {
+        via: Equivalence,
+    },
+    /// A local branch that is written directly by fetching from a remote, as in a mirror clone.
+    MirrorBranch {
+        remote: String,
+        /// True if the remote was verified to have the same commit for the branch. Commits pushed
+        /// into a mirror don't reach the remote it mirrors, so nothing else shows they are there.
+        verified: bool,
+    },
 }
 
 impl BranchStatus {
@@ -43,158 +661,261 @@ impl BranchStatus {
         BranchStatus::LocalBranch { merged_in_remote }
     }
 
-    fn merged_in_upstream(&self) -> bool {
+    fn new_equivalent_branch(via: Equivalence) -> Self {
+        BranchStatus::EquivalentInRemote { via }
+    }
+
+    fn new_mirror_branch(remote: String, verified: bool) -> Self {
+        BranchStatus::MirrorBranch { remote, verified }
+    }
+
+    /// True if every commit on this branch is contained in some remote branch.
+    pub fn merged_in_upstream(&self) -> bool {
         let tracking_status = match self {
             BranchStatus::TrackingBranch(status) => status,
             BranchStatus::LocalBranch { merged_in_remote } => return *merged_in_remote,
+            BranchStatus::EquivalentInRemote { .. } => return true,
+            BranchStatus::MirrorBranch { verified, .. } => return *verified,
         };
 
-        use TrackingStatus::*;
-        match tracking_status {
-            Diverged | Ahead => false,
+        use TrackingState::*;
+        match tracking_status.state() {
+            Unrelated | Diverged | Ahead => false,
             Behind | Current => true,
         }
     }
 }
 
+/// How the changes of a local branch ended up on a remote without the branch being merged.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all="snake_case")]
+pub enum Equivalence {
+    /// Every commit on the branch was rebased or cherry-picked onto a remote branch.
+    Rebased,
+    /// The branch was squashed into a single commit on a remote branch.
+    Squashed,
+}
+
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
-pub enum TrackingStatus {
+#[serde(rename_all="snake_case")]
+pub struct TrackingStatus {
+    /// Shorthand name of the upstream branch, e.g. `origin/master`.
+    pub upstream: String,
+    /// Number of commits on the local branch that are not on the upstream.
+    pub ahead: usize,
+    /// Number of commits on the upstream that are not on the local branch.
+    pub behind: usize,
+    /// True if the branch and its upstream have no common ancestor.
+    ///
+    /// In this case `ahead` and `behind` count the entire history of each side.
+    #[serde(default, skip_serializing_if="std::ops::Not::not")]
+    pub unrelated: bool,
+    /// True if the remotes were verified and the upstream no longer matches the remote, so
+    /// `ahead` and `behind` are out of date.
+    #[serde(default, skip_serializing_if="std::ops::Not::not")]
+    pub stale: bool,
+}
+
+impl TrackingStatus {
+    pub fn state(&self) -> TrackingState {
+        if self.unrelated {
+            return TrackingState::Unrelated;
+        }
+
+        match (self.ahead, self.behind) {
+            (0, 0) => TrackingState::Current,
+            (_, 0) => TrackingState::Ahead,
+            (0, _) => TrackingState::Behind,
+            (_, _) => TrackingState::Diverged,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TrackingState {
+    /// The branch and its upstream share no history.
+    Unrelated,
     Diverged,
     Ahead,
     Behind,
     Current,
 }
 
-pub fn get_status_path<P: AsRef<Path>>(path: P) -> Result<RepoStatus, git2::Error> {
-    let mut repo = Repository::open(path)?;
+/// Open the repository at `path`, distinguishing a path that isn't a repo from other failures.
+pub fn open_repo<P: AsRef<Path>>(path: P) -> Result<Repository, Error> {
+    let path = path.as_ref();
+    Repository::open(path).map_err(|err| {
+        if err.code() == git2::ErrorCode::NotFound {
+            Error::NotARepository(path.to_path_buf())
+        } else {
+            err.into()
+        }
+    })
+}
+
+pub fn get_status_path<P: AsRef<Path>>(path: P) -> Result<RepoStatus, Error> {
+    let mut repo = open_repo(path)?;
     get_status(&mut repo)
 }
 
-pub fn get_status(repo: &mut Repository) -> Result<RepoStatus, git2::Error> {
+pub fn get_status(repo: &mut Repository) -> Result<RepoStatus, Error> {
+    get_status_with_options(repo, &StatusOptions::default())
+}
+
+pub fn get_status_with_options(
+    repo: &mut Repository,
+    options: &StatusOptions,
+) -> Result<RepoStatus, Error> {
     let bare = repo.is_bare();
 
     let remotes = {
         let mut out = Vec::new();
         let remotes = repo.remotes()?;
-        for remote in remotes.iter() {
-            let name = remote.map(|s| s.to_string())
-                .unwrap_or_else(|| "[non unicode]".to_string());
-            out.push(Remote { name })
+        for (i, remote) in remotes.iter().enumerate() {
+            let name = match remote {
+                Some(name) => name.to_string(),
+                None => {
+                    let name = remotes.get_bytes(i).unwrap_or_default();
+                    return Err(Error::NonUtf8Name(String::from_utf8_lossy(name).into_owned()));
+                },
+            };
+            let remote = repo.find_remote(&name)?;
+            let last_fetch = last_fetch(repo, &remote)?;
+            let mut remote = Remote::new(name, &remote, repo.workdir().unwrap_or_else(|| repo.path()));
+            remote.last_fetch = last_fetch;
+            if options.verify_remotes {
+                remote.verification = Some(verify::verify_remote(repo, &remote.name));
+            }
+            out.push(remote)
         }
         out
     };
+    let stale_refs: HashSet<_> = remotes.iter()
+        .filter_map(|remote| remote.verification.as_ref())
+        .flat_map(|verification| &verification.stale_refs)
+        .map(|stale| stale.local_ref.as_str())
+        .collect();
 
-    // TODO: figure out if there should be an early return at all
-    if bare {
-        return Ok(RepoStatus {
-            bare,
-            remotes,
-            clean_status: true,
-            clean_state: true,
-            stashes: 0,
-            branches: HashMap::new(),
-        });
-    }
-
-    // check that all files are current or ignored
-    let mut clean_status = true;
-    {
-        let statuses = repo.statuses(None)?;
-
-        let cmp_status = git2::Status::CURRENT | git2::Status::IGNORED;
-        for entry in statuses.iter() {
-            if !entry.status().intersects(cmp_status) {
-                clean_status = false;
-                break;
-            }
-        }
-    }
+    // a bare repo has no working tree or index to check
+    let files = if bare {
+        FileStatus::default()
+    } else {
+        get_file_status(repo, options.include_paths)?
+    };
 
-    let clean_state = repo.state() == RepositoryState::Clean;
+    let operation = operation::current_operation(repo)?;
 
-    let mut stashes = 0;
-    repo.stash_foreach(|_, _, _| {
-        stashes += 1;
-        true
-    })?;
+    let stashes = stash::list_stashes(repo)?;
 
     let mut local_only_branches = Vec::new();
     let mut branches = HashMap::new();
 
-    let mut branch_iter = repo.branches(Some(git2::BranchType::Local))?;
-    for branch in branch_iter {
+    let git_remotes = remotes.iter()
+        .map(|remote| repo.find_remote(&remote.name))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    for branch in repo.branches(Some(git2::BranchType::Local))? {
         let (branch, _) = branch?;
         let tracking_status = match branch.upstream() {
             Ok(upstream) => {
-                // get the merge base
                 let upstream_commit = upstream.get().peel_to_commit()?.id();
                 let branch_commit = branch.get().peel_to_commit()?.id();
 
-                if upstream_commit == branch_commit {
-                    TrackingStatus::Current
-                } else {
-                    // TODO: The error codes aren't document for this...
-                    let ancestor = repo.merge_base(branch_commit, upstream_commit)
-                        .expect("merge base error");
-                    if ancestor == branch_commit {
-                        // if the merge base is branch, then branch is behind the upstream
-                        TrackingStatus::Behind
-                    } else if ancestor == upstream_commit {
-                        // if the merge base is the upstream, then branch is ahead
-                        TrackingStatus::Ahead
-                    } else {
-                        // if it is neither, the branches have diverged
-                        TrackingStatus::Diverged
+                let (ahead, behind) = repo.graph_ahead_behind(branch_commit, upstream_commit)?;
+
+                // only a diverged branch can possibly be unrelated to its upstream
+                let unrelated = if ahead > 0 && behind > 0 {
+                    match merge_base(repo, branch_commit, upstream_commit) {
+                        Ok(_) => false,
+                        Err(Error::UnrelatedHistories { .. }) => true,
+                        Err(err) => return Err(err),
                     }
+                } else {
+                    false
+                };
+
+                TrackingStatus {
+                    upstream: branch_name(&upstream)?,
+                    ahead,
+                    behind,
+                    unrelated,
+                    stale: upstream.get().name().is_some_and(|name| stale_refs.contains(name)),
                 }
             },
             Err(err) => {
                 if err.code() != git2::ErrorCode::NotFound {
-                    return Err(err);
+                    return Err(err.into());
                 }
+                // a remote that fetches directly into the branch, e.g. `git clone --mirror`
+                let refname = branch.get().name().unwrap_or_default();
+                let mirror = git_remotes.iter()
+                    .find(|remote| fetches_into(remote, refname));
+                if let Some(remote) = mirror {
+                    let remote = remote.name().unwrap_or_default().to_string();
+                    let verified = remotes.iter()
+                        .find(|verified| verified.name == remote)
+                        .and_then(|verified| verified.verification.as_ref())
+                        .is_some_and(|verification| {
+                            verification.error.is_none() && !stale_refs.contains(refname)
+                        });
+                    let status = BranchStatus::new_mirror_branch(remote, verified);
+                    branches.insert(branch_name(&branch)?, status);
+                    continue;
+                }
+
                 // add the branch to an auxillary list to be checked
                 local_only_branches.push(branch);
                 continue;
             }
         };
 
-        let name = branch.name()?
-            .unwrap_or_else(|| "[non utf-8]")
-            .to_string();
+        let name = branch_name(&branch)?;
 
         let status = BranchStatus::new_tracking_branch(tracking_status);
         branches.insert(name, status);
     }
 
+    let mut remote_tips = repo.branches(Some(git2::BranchType::Remote))?
+        .map(|remote_branch| Ok(remote_branch?.0.get().peel_to_commit()?.id()))
+        .collect::<Result<Vec<_>, Error>>()?;
+    // a mirrored branch the remote was just verified to have is as good as a remote branch
+    for (name, status) in &branches {
+        if let BranchStatus::MirrorBranch { verified: true, .. } = status {
+            let branch = repo.find_branch(name, git2::BranchType::Local)?;
+            remote_tips.push(branch.get().peel_to_commit()?.id());
+        }
+    }
+
     // loop over remote branches and check if the auxillary branches are merged
     // this will catch branches that are not tracking branches, but were in fact merged
-    branch_iter = repo.branches(Some(git2::BranchType::Remote))?;
-    for remote_branch in branch_iter {
-        // get the remote commit
-        let (remote_branch, _) = remote_branch?;
-        let remote_commit = remote_branch.get().peel_to_commit()?.id();
-
+    for &remote_commit in &remote_tips {
         // retain only branches that aren't merged in the remote branch
         let mut iter_err = None;
         local_only_branches.retain(|branch| {
             let commit = match branch.get().peel_to_commit() {
                 Ok(commit) => commit.id(),
                 Err(err) => {
-                    iter_err = Some(err);
+                    iter_err = Some(err.into());
                     return false;
                 },
             };
 
             // find the merge base (common ancestor)
-            let ancestor = repo.merge_base(commit, remote_commit)
-                .expect("merge base error");
+            let ancestor = match merge_base(repo, commit, remote_commit) {
+                Ok(ancestor) => ancestor,
+                // unrelated to this remote branch, so certainly not merged in it
+                Err(Error::UnrelatedHistories { .. }) => return true,
+                Err(err) => {
+                    iter_err = Some(err);
+                    return false;
+                },
+            };
 
             if ancestor == commit {
                 // if local branch is merged in the remote branch, record the existence
                 // of the local branch, and return false since this branch is not local only
-                let name = match branch.name() {
-                    Ok(branch) => branch.unwrap_or_else(|| "[non utf-8]")
-                        .to_string(),
+                let name = match branch_name(branch) {
+                    Ok(name) => name,
                     Err(err) => {
                         iter_err = Some(err);
                         return false;
@@ -216,20 +937,263 @@ pub fn get_status(repo: &mut Repository) -> Result<RepoStatus, git2::Error> {
         }
     }
 
-    // record the remaining branches as certainly local
+    // the remaining branches may still have been rebased or squashed onto a remote branch,
+    // otherwise they are certainly local
+    let mut patch_ids = patch_id::PatchIdCache::default();
     for local_branch in local_only_branches {
-        let name = local_branch.name()?
-            .unwrap_or_else(|| "[non utf-8]")
-            .to_string();
-        branches.insert(name, BranchStatus::new_local_branch(false));
+        let name = branch_name(&local_branch)?;
+        let commit = local_branch.get().peel_to_commit()?.id();
+        let status = match patch_id::find_equivalent(repo, &mut patch_ids, commit, &remote_tips)? {
+            Some(via) => BranchStatus::new_equivalent_branch(via),
+            None => BranchStatus::new_local_branch(false),
+        };
+        branches.insert(name, status);
     }
 
+    let head = head_status(repo, &remote_tips)?;
+    let unpushed_tags = tags::unpushed_tags(repo, &git_remotes, &remote_tips)?;
+    let submodules = if bare {
+        Vec::new()
+    } else {
+        submodule::submodule_statuses(repo, options)?
+    };
+    let reflog_commits = if options.deep {
+        Some(reflog::reflog_commits(repo)?)
+    } else {
+        None
+    };
+    let worktrees = worktree::worktree_statuses(repo, options, &remote_tips)?;
+    let lfs = lfs::lfs_status(repo, &remote_tips)?;
+
     Ok(RepoStatus {
-        bare: false,
-        clean_status,
-        clean_state,
+        bare,
+        head,
+        files,
+        operation,
         stashes,
+        unpushed_tags,
+        reflog_commits,
         remotes,
         branches,
+        submodules,
+        worktrees,
+        lfs,
     })
 }
+
+/// Work out what HEAD points to, checking a detached HEAD against the tips of the remote
+/// branches.
+fn head_status(repo: &Repository, remote_tips: &[git2::Oid]) -> Result<Head, Error> {
+    let head = repo.find_reference("HEAD")?;
+    if let Some(target) = head.symbolic_target_bytes() {
+        let target = std::str::from_utf8(target)
+            .map_err(|_| Error::NonUtf8Name(String::from_utf8_lossy(target).into_owned()))?;
+        let name = target.strip_prefix("refs/heads/").unwrap_or(target).to_string();
+        return Ok(match repo.refname_to_id(target) {
+            Ok(_) => Head::Branch { name },
+            Err(err) if err.code() == git2::ErrorCode::NotFound => Head::Unborn { name },
+            Err(err) => return Err(err.into()),
+        });
+    }
+
+    let commit = head.peel_to_commit()?.id();
+    Ok(Head::Detached {
+        commit: commit.to_string(),
+        merged_in_remote: contained_in_any(repo, commit, remote_tips)?,
+    })
+}
+
+/// True if `commit` is one of `tips` or an ancestor of one.
+fn contained_in_any(
+    repo: &Repository,
+    commit: git2::Oid,
+    tips: &[git2::Oid],
+) -> Result<bool, Error> {
+    for &tip in tips {
+        if tip == commit || repo.graph_descendant_of(tip, commit)? {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Find the best common ancestor of two commits.
+///
+/// Returns `Error::UnrelatedHistories` if there is none.
+fn merge_base(repo: &Repository, one: git2::Oid, two: git2::Oid) -> Result<git2::Oid, Error> {
+    match repo.merge_base(one, two) {
+        Ok(ancestor) => Ok(ancestor),
+        Err(err) if err.code() == git2::ErrorCode::NotFound =>
+            Err(Error::UnrelatedHistories { one, two }),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// The git dir shared by every worktree of the repo whose git dir is `git_dir`, which holds its
+/// refs, config and objects. Only differs from `git_dir` for a linked worktree.
+fn common_dir(git_dir: &Path) -> PathBuf {
+    match std::fs::read_to_string(git_dir.join("commondir")) {
+        Ok(common_dir) => git_dir.join(common_dir.trim()),
+        Err(_) => git_dir.to_path_buf(),
+    }
+}
+
+/// True if fetching from `remote` updates the local reference `refname`.
+fn fetches_into(remote: &git2::Remote, refname: &str) -> bool {
+    remote.refspecs().any(|spec| {
+        matches!(spec.direction(), git2::Direction::Fetch) && spec.dst_matches(refname)
+    })
+}
+
+/// Work out when `remote` was last fetched from, using `FETCH_HEAD` and the reflogs of the refs
+/// it fetches into.
+fn last_fetch(repo: &Repository, remote: &git2::Remote) -> Result<Option<i64>, Error> {
+    let mut last = None;
+
+    // FETCH_HEAD is rewritten by every fetch, even one that changes nothing, but only for the
+    // remote that was fetched last
+    if let Some(url) = remote.url() {
+        let fetch_head = repo.path().join("FETCH_HEAD");
+        if let Ok(contents) = std::fs::read_to_string(&fetch_head) {
+            let url = fetch_head_url(url);
+            let fetched = contents.lines()
+                .any(|line| line.rsplit(" of ").next().is_some_and(|of| fetch_head_url(of) == url));
+            if fetched {
+                last = std::fs::metadata(&fetch_head)
+                    .and_then(|meta| meta.modified())
+                    .ok()
+                    .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+                    .map(|time| time.as_secs() as i64);
+            }
+        }
+    }
+
+    for reference in repo.references()? {
+        let reference = reference?;
+        let name = match reference.name() {
+            Some(name) if fetches_into(remote, name) => name,
+            _ => continue,
+        };
+        // bare repos don't keep reflogs by default
+        let reflog = match repo.reflog(name) {
+            Ok(reflog) => reflog,
+            Err(_) => continue,
+        };
+        let fetched = reflog.iter()
+            .find(|entry| {
+                let message = entry.message().unwrap_or_default();
+                ["fetch", "pull", "clone"].iter().any(|op| message.starts_with(op))
+            })
+            .map(|entry| entry.committer().when().seconds());
+        last = last.max(fetched);
+    }
+
+    Ok(last)
+}
+
+/// Normalize a URL the way git does before writing it to `FETCH_HEAD`, where credentials are
+/// removed and a trailing `/` or `.git` is dropped.
+fn fetch_head_url(url: &str) -> String {
+    let url = without_userinfo(url);
+    let url = url.trim_end_matches('/');
+    url.strip_suffix(".git").unwrap_or(url).to_string()
+}
+
+/// Remove the `user[:password]@` from a URL, like git's `transport_anonymize_url`.
+fn without_userinfo(url: &str) -> Cow<'_, str> {
+    let at = match url.find('@') {
+        Some(at) => at,
+        None => return Cow::Borrowed(url),
+    };
+    // a local path, which has no colon before its first slash
+    match (url.find(':'), url.find('/')) {
+        (None, _) => return Cow::Borrowed(url),
+        (Some(colon), Some(slash)) if slash < colon => return Cow::Borrowed(url),
+        _ => {},
+    }
+
+    match url.find("://") {
+        Some(scheme) => {
+            let authority = scheme + 3;
+            // an @ after the host is part of the path
+            let path = url[authority..].find('/').map(|slash| authority + slash);
+            if path.is_some_and(|path| path < at) {
+                return Cow::Borrowed(url);
+            }
+            Cow::Owned(format!("{}{}", &url[..authority], &url[at + 1..]))
+        },
+        // scp-like syntax, `user@host:path`
+        None if url[at + 1..].contains(':') => Cow::Borrowed(&url[at + 1..]),
+        None => Cow::Borrowed(url),
+    }
+}
+
+fn branch_name(branch: &git2::Branch) -> Result<String, Error> {
+    match branch.name()? {
+        Some(name) => Ok(name.to_string()),
+        None => {
+            let name = branch.name_bytes()?;
+            Err(Error::NonUtf8Name(String::from_utf8_lossy(name).into_owned()))
+        },
+    }
+}
+
+fn get_file_status(repo: &Repository, include_paths: bool) -> Result<FileStatus, git2::Error> {
+    use git2::Status;
+
+    let mut opts = git2::StatusOptions::new();
+    opts.include_untracked(true)
+        .recurse_untracked_dirs(true)
+        .include_ignored(false)
+        .renames_head_to_index(true)
+        .renames_index_to_workdir(true);
+
+    // a submodule counts as modified when its own files are, but those are reported in its own
+    // status, so here only checking out a different commit in it is a change
+    let mut submodules = HashMap::new();
+    for submodule in repo.submodules()? {
+        if let Some(name) = submodule.name() {
+            submodules.insert(submodule.path().to_path_buf(), name.to_string());
+        }
+    }
+
+    let mut files = FileStatus::default();
+    let statuses = repo.statuses(Some(&mut opts))?;
+    for entry in statuses.iter() {
+        let mut status = entry.status();
+        if status.contains(Status::WT_MODIFIED) {
+            if let Some(name) = entry.path().and_then(|path| submodules.get(Path::new(path))) {
+                let submodule = repo.submodule_status(name, git2::SubmoduleIgnore::Dirty)?;
+                if !submodule.contains(git2::SubmoduleStatus::WD_MODIFIED) {
+                    status.remove(Status::WT_MODIFIED);
+                }
+            }
+        }
+        let path = if include_paths {
+            Some(entry.path().unwrap_or("[non utf-8]"))
+        } else {
+            None
+        };
+
+        // a conflicted entry also reports index/worktree flags that aren't meaningful on their own
+        if status.contains(Status::CONFLICTED) {
+            files.record(FileChange::Conflicted, path);
+            continue;
+        }
+
+        let categories = [
+            (Status::INDEX_NEW | Status::INDEX_MODIFIED | Status::INDEX_TYPECHANGE, FileChange::Staged),
+            (Status::WT_MODIFIED | Status::WT_TYPECHANGE, FileChange::Modified),
+            (Status::INDEX_DELETED | Status::WT_DELETED, FileChange::Deleted),
+            (Status::INDEX_RENAMED | Status::WT_RENAMED, FileChange::Renamed),
+            (Status::WT_NEW, FileChange::Untracked),
+        ];
+        for &(flags, change) in &categories {
+            if status.intersects(flags) {
+                files.record(change, path);
+            }
+        }
+    }
+
+    Ok(files)
+}
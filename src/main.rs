@@ -1,17 +1,45 @@
 use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::Arc;
 
-use virtual_repo_hub::{get_status, BranchStatus, TrackingStatus};
+use virtual_repo_hub::{
+    open_repo,
+    Error,
+    FileChange,
+    RepoStatus,
+    StatusOptions,
+};
+use virtual_repo_hub::backup::{
+    check_backup,
+    BackupPolicy,
+    BackupVerdict,
+};
+use virtual_repo_hub::batch::map_repos;
+use virtual_repo_hub::index;
+use virtual_repo_hub::index::Index;
+use virtual_repo_hub::stash;
+use virtual_repo_hub::discover::{
+    discover_repos,
+    DiscoverOptions,
+};
+use virtual_repo_hub::report::{
+    BackupReport,
+    StatusReport,
+};
 use virtual_repo_hub::config::{
     Config,
     ConfigError,
     config_path,
 };
 
-use git2::Repository;
+use serde::Serialize;
 use clap::{
     App,
     AppSettings,
     Arg,
+    ArgGroup,
+    ArgMatches,
     SubCommand,
 };
 
@@ -25,21 +53,153 @@ const STAR_HELP: &str = "Add a directory to your starred directories.
 
 Starred directories are directories that largely contain git repositories and/or that you would like
 to track. VRH can use starred directory names as aliases, and when a directory is expected but not
-provided, starred directories will often be used as defaults for VRH commands.";
+provided, starred directories will often be used as defaults for VRH commands.
+
+Exit codes:
+    0  the starred directories were updated
+    3  internal error, such as failing to save the configuration
+    4  invalid arguments, such as DIR not being a directory or ALIAS being taken";
+
+const STARS_ABOUT: &str = "List your starred directories.";
+
+const UNSTAR_ABOUT: &str = "Remove a directory from your starred directories.";
+const UNSTAR_HELP: &str = "Remove a directory from your starred directories.
+
+The directory itself is left alone, only the alias is forgotten.
+
+Exit codes:
+    0  the starred directories were updated
+    3  internal error, such as failing to save the configuration
+    4  invalid arguments, such as no directory being starred as ALIAS";
+
+const RENAME_STAR_ABOUT: &str = "Change the alias of a starred directory.";
+const RENAME_STAR_HELP: &str = "Change the alias of a starred directory.
+
+Exit codes:
+    0  the starred directories were updated
+    3  internal error, such as failing to save the configuration
+    4  invalid arguments, such as ALIAS being unknown or NEW_ALIAS being taken";
+
+const RELOCATE_STAR_ABOUT: &str = "Point a starred alias at a different directory.";
+const RELOCATE_STAR_HELP: &str = "Point a starred alias at a different directory.
+
+Use this after moving a starred directory, so that the alias keeps working.
+
+Exit codes:
+    0  the starred directories were updated
+    3  internal error, such as failing to save the configuration
+    4  invalid arguments, such as ALIAS being unknown or DIR not being a directory";
+
+const INDEX_ABOUT: &str = "Record every repo in your starred directories.";
+const INDEX_HELP: &str = "Record every repo in your starred directories.
+
+Each repo's remotes, default branch, last commit time and status are saved with your configuration,
+replacing whatever was recorded by the last run.
+
+Exit codes:
+    0  every starred directory was indexed
+    2  some paths could not be read
+    3  internal error
+    4  invalid arguments, or no directories are starred
+
+Repos that can't be read are recorded with their error and don't change the exit code.";
+
+const STASH_EXPORT_ABOUT: &str = "Turn a repo's stashes into branches or a bundle that can be backed up.";
+const STASH_EXPORT_HELP: &str = "Turn a repo's stashes into branches or a bundle that can be backed up.
+
+Stashes are never pushed. With --branches, a branch is created for every stash, pointing at the
+stash itself, so pushing the branches backs the stashes up and `git stash apply <branch>` restores
+one. With --bundle, the stashes are written to a bundle file instead, which holds everything needed
+to restore them with `git fetch <bundle> 'refs/heads/*:refs/heads/*'`.
+
+Branches are named PREFIX followed by the start of the stash's commit id.";
 
 const BACKUPCHECK_ABOUT: &str = "Check if a directory or git repo is fully backed up.";
-const BACKUPCHECK_HELP: &str = "Check if a directory or git repo is fully backed up.";
+const BACKUPCHECK_HELP: &str = "Check if a directory or git repo is fully backed up.
+
+If DIR is not a git repo, every repo found below it is checked instead. DIR may also be the alias
+of a starred directory, and if it is omitted every starred directory is checked.
 
-fn main() -> Result<(), i32> {
-    let config_path = config_path().unwrap();
-    let mut config = match Config::load(&config_path) {
+A remote that is another folder on the same disk doesn't protect against losing the disk. Repos
+without a remote on another filesystem or host get a warning, or fail with --require-off-device.
+Only filesystems can be told apart, so a remote on another partition of the same disk still counts
+as off device.
+
+With --deep, the reflogs of HEAD and every branch are searched for commits that nothing else refers
+to, such as amended commits or the tips of deleted branches. They are only a warning, but `git gc`
+deletes them once their reflog entries expire.
+
+Git LFS doesn't record which objects it uploaded, so an LFS object is assumed to be on a remote once
+a commit that refers to it is in a remote branch. That only holds while the LFS pre-push hook is
+installed, so repos without it fail.
+
+Exit codes:
+    0  every repo is backed up
+    1  some repos have work that is not backed up
+    2  some paths could not be read or are not git repos
+    3  internal error
+    4  invalid arguments
+
+When several apply, the highest exit code is used.";
+
+/// Result of a command, ordered from best to worst so that sweeps can report the worst outcome.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Outcome {
+    BackedUp = 0,
+    AtRisk = 1,
+    Unreadable = 2,
+    InternalError = 3,
+    /// The command was used wrongly, e.g. with an unknown alias. Never the result of a sweep.
+    InvalidUsage = 4,
+}
+
+impl Outcome {
+    fn of_error(err: &Error) -> Outcome {
+        if err.is_unreadable() {
+            Outcome::Unreadable
+        } else {
+            Outcome::InternalError
+        }
+    }
+
+    fn of_verdict(verdict: &Result<BackupVerdict, Error>) -> Outcome {
+        match verdict {
+            Ok(verdict) if verdict.backed_up => Outcome::BackedUp,
+            Ok(_) => Outcome::AtRisk,
+            Err(err) => Outcome::of_error(err),
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    ExitCode::from(run() as u8)
+}
+
+fn run() -> Outcome {
+    let config_path = match config_path() {
+        Ok(path) => path,
+        Err(err) => {
+            eprintln!("Failed to find config directory: {}", err);
+            return Outcome::InternalError;
+        },
+    };
+    let loaded = match Config::load(&config_path) {
+        Err(Error::Config(ConfigError::NotFound)) => Config::init(&config_path)
+            .map(|config| config.expect("config was not found but appears to be initialized")),
+        loaded => loaded,
+    };
+    let mut config = match loaded {
         Ok(config) => config,
-        Err(ConfigError::NotFound) => Config::init(&config_path)
-            .expect("Failed to init config")
-            .unwrap(),
-        Err(err) => panic!("Failed to find or init config: {:?}", err),
+        Err(err) => {
+            eprintln!("Failed to find or init config: {}", err);
+            return Outcome::InternalError;
+        },
     };
 
+    let file_change_names: Vec<_> = FileChange::ALL.iter()
+        .map(|change| change.as_str())
+        .collect();
+
     let app = App::new("Virtual Repo Hub")
         .version("0.1")
         .set_term_width(80)
@@ -52,140 +212,649 @@ fn main() -> Result<(), i32> {
                 .required(true)
                 .help("directory to star"))
             .arg(Arg::with_name("ALIAS")
-                .help("alias to represent the starred directory")))
+                .help("alias to represent the starred directory [default: name of DIR]")))
+        .subcommand(SubCommand::with_name("stars")
+            .about(STARS_ABOUT))
+        .subcommand(SubCommand::with_name("unstar")
+            .about(UNSTAR_ABOUT)
+            .help(UNSTAR_HELP)
+            .arg(Arg::with_name("ALIAS")
+                .required(true)
+                .help("alias of the starred directory")))
+        .subcommand(SubCommand::with_name("rename-star")
+            .about(RENAME_STAR_ABOUT)
+            .help(RENAME_STAR_HELP)
+            .arg(Arg::with_name("ALIAS")
+                .required(true)
+                .help("current alias of the starred directory"))
+            .arg(Arg::with_name("NEW_ALIAS")
+                .required(true)
+                .help("alias to use instead")))
+        .subcommand(SubCommand::with_name("relocate-star")
+            .about(RELOCATE_STAR_ABOUT)
+            .help(RELOCATE_STAR_HELP)
+            .arg(Arg::with_name("ALIAS")
+                .required(true)
+                .help("alias of the starred directory"))
+            .arg(Arg::with_name("DIR")
+                .required(true)
+                .help("new location of the directory")))
+        .subcommand(SubCommand::with_name("index")
+            .about(INDEX_ABOUT)
+            .help(INDEX_HELP)
+            .args(&sweep_args()))
         .subcommand(SubCommand::with_name("status")
             .about(STATUS_ABOUT)
             .help(STATUS_HELP)
             .arg(Arg::with_name("DIR")
-                .required(true)))
+                .help("repo, directory of repos or starred alias [default: all starred directories]"))
+            .arg(Arg::with_name("paths")
+                .long("paths")
+                .help("list every changed file, not just the counts"))
+            .arg(verify_arg())
+            .arg(deep_arg())
+            .args(&sweep_args())
+            .arg(format_arg()))
+        .subcommand(SubCommand::with_name("stash-export")
+            .about(STASH_EXPORT_ABOUT)
+            .help(STASH_EXPORT_HELP)
+            .arg(Arg::with_name("DIR")
+                .required(true)
+                .help("repo whose stashes to export"))
+            .arg(Arg::with_name("branches")
+                .long("branches")
+                .help("create a branch for every stash"))
+            .arg(Arg::with_name("bundle")
+                .long("bundle")
+                .takes_value(true)
+                .value_name("FILE")
+                .help("write the stashes to a bundle file"))
+            .group(ArgGroup::with_name("target")
+                .args(&["branches", "bundle"])
+                .required(true))
+            .arg(Arg::with_name("prefix")
+                .long("prefix")
+                .takes_value(true)
+                .default_value("stash/")
+                .help("prefix of the branch names")))
         .subcommand(SubCommand::with_name("backupcheck")
             .about(BACKUPCHECK_ABOUT)
             .help(BACKUPCHECK_HELP)
             .arg(Arg::with_name("DIR")
-                .required(true)));
+                .help("repo, directory of repos or starred alias [default: all starred directories]"))
+            .arg(Arg::with_name("allow-dirty")
+                .long("allow-dirty")
+                .takes_value(true)
+                .multiple(true)
+                .use_delimiter(true)
+                .possible_values(&file_change_names)
+                .help("kinds of uncommitted file changes that don't fail the check"))
+            .arg(Arg::with_name("require-off-device")
+                .long("require-off-device")
+                .help("fail repos whose remotes are all on the same filesystem, instead of warning"))
+            .arg(Arg::with_name("max-fetch-age")
+                .long("max-fetch-age")
+                .takes_value(true)
+                .value_name("AGE")
+                .validator(|v| parse_age(&v).map(|_| ()))
+                .help("warn about remotes not fetched from within AGE, e.g. 12h or 30d"))
+            .arg(verify_arg())
+            .arg(deep_arg())
+            .args(&sweep_args())
+            .arg(format_arg()));
 
-    let matches = app.get_matches();
+    // clap exits with 1 on usage errors, which backupcheck uses for repos at risk
+    let matches = match app.get_matches_safe() {
+        Ok(matches) => matches,
+        Err(err) if err.use_stderr() => {
+            eprintln!("{}", err.message);
+            return Outcome::InvalidUsage;
+        },
+        Err(err) => {
+            println!("{}", err.message);
+            return Outcome::BackedUp;
+        },
+    };
 
     match matches.subcommand() {
         ("star", Some(matches)) => {
             let dir = matches.value_of_os("DIR")
                 .unwrap();
             let alias = matches.value_of_os("ALIAS");
-            config.star(&dir, alias);
-            config.save(&config_path)
-                .expect("Failed to save configuration");
+            let star = config.star(dir, alias)
+                .map(|alias| println!("Starred {} as {}", Path::new(dir).display(), alias));
+            save_config(&config, &config_path, star)
         },
-        ("status", Some(matches)) => {
+        ("stars", Some(_)) => {
+            for (alias, path) in config.starred() {
+                if path.is_dir() {
+                    println!("{}\t{}", alias, path.display());
+                } else {
+                    println!("{}\t{} (missing)", alias, path.display());
+                }
+            }
+
+            Outcome::BackedUp
+        },
+        ("unstar", Some(matches)) => {
+            let alias = matches.value_of_os("ALIAS")
+                .unwrap();
+            let unstar = config.unstar(alias)
+                .map(|path| println!("Unstarred {} ({})", alias.to_string_lossy(), path.display()));
+            save_config(&config, &config_path, unstar)
+        },
+        ("rename-star", Some(matches)) => {
+            let alias = matches.value_of_os("ALIAS")
+                .unwrap();
+            let new_alias = matches.value_of_os("NEW_ALIAS")
+                .unwrap();
+            let rename = config.rename_star(alias, new_alias);
+            save_config(&config, &config_path, rename)
+        },
+        ("relocate-star", Some(matches)) => {
+            let alias = matches.value_of_os("ALIAS")
+                .unwrap();
             let dir = matches.value_of_os("DIR")
                 .unwrap();
-            let mut repo = match Repository::open(dir.clone()) {
-                Ok(repo) => repo,
-                Err(_) => {
-                    eprintln!("Failed to open a git repo at {:?}", &dir);
-                    return Err(-1);
+            let relocate = config.relocate_star(alias, dir);
+            save_config(&config, &config_path, relocate)
+        },
+        ("index", Some(matches)) => {
+            let starred = config.starred();
+            if starred.is_empty() {
+                eprintln!("There are no starred directories to index");
+                return Outcome::InvalidUsage;
+            }
+
+            let index_path = config.index_path(&config_path);
+            let previous = if matches.is_present("refresh") {
+                None
+            } else {
+                Some(load_index(&index_path))
+            };
+
+            let (index, errors) = index::scan(
+                &starred,
+                &discover_options(matches),
+                jobs(matches),
+                previous.as_ref(),
+            );
+            let mut outcome = Outcome::BackedUp;
+            for err in errors {
+                eprintln!("{}", err);
+                outcome = outcome.max(Outcome::of_error(&err));
+            }
+            for repo in &index.repos {
+                if let Some(err) = &repo.error {
+                    eprintln!("Failed to read repo {}: {}", repo.path, err);
                 }
+            }
+
+            if save_index(&index, &index_path) != Outcome::BackedUp {
+                return Outcome::InternalError;
+            }
+            println!(
+                "Indexed {} repos in {} starred directories",
+                index.repos.len(),
+                starred.len(),
+            );
+
+            outcome
+        },
+        ("stash-export", Some(matches)) => {
+            let dir = Path::new(matches.value_of_os("DIR").unwrap());
+            let prefix = matches.value_of("prefix").unwrap();
+            let exported = open_repo(dir).and_then(|mut repo| {
+                match matches.value_of_os("bundle") {
+                    Some(bundle) => stash::stashes_to_bundle(&mut repo, prefix, Path::new(bundle)),
+                    None => stash::stashes_to_branches(&mut repo, prefix),
+                }
+            });
+
+            match exported {
+                Ok(names) => {
+                    for name in &names {
+                        println!("Exported stash as {}", name);
+                    }
+                    if names.is_empty() {
+                        println!("No new stashes to export in {}", dir.display());
+                    }
+                    Outcome::BackedUp
+                },
+                Err(err) => {
+                    eprintln!("Failed to export stashes from {}: {}", dir.display(), err);
+                    Outcome::of_error(&err)
+                },
+            }
+        },
+        ("status", Some(matches)) => {
+            let targets = match targets(&config, matches.value_of_os("DIR")) {
+                Some(targets) => targets,
+                None => return Outcome::Unreadable,
             };
+            let format = matches.value_of("format")
+                .unwrap()
+                .parse()
+                .unwrap();
+            let options = StatusOptions {
+                include_paths: matches.is_present("paths"),
+                verify_remotes: matches.is_present("verify"),
+                deep: matches.is_present("deep"),
+            };
+
+            let index_path = config.index_path(&config_path);
+            let mut index = load_index(&index_path);
 
-            let status = get_status(&mut repo)
-                .expect("Failed to get repo status");
+            let mut printer = ReportPrinter::new(format, print_status_text);
+            let sweep = sweep(&targets, matches, &options, &mut index, &mut printer, |path, alias, status| {
+                let outcome = match &status {
+                    Ok(_) => Outcome::BackedUp,
+                    Err(err) => Outcome::of_error(err),
+                };
+                let mut report = StatusReport::new(path, status);
+                report.alias = alias.map(str::to_string);
+                (report, outcome)
+            });
+            printer.finish();
 
-            println!("Got repo status: {:?}", &status);
+            let (mut outcome, index_changed) = sweep;
+            if index_changed {
+                outcome = outcome.max(save_index(&index, &index_path));
+            }
+            outcome
         },
         ("backupcheck", Some(matches)) => {
-            let dir = matches.value_of_os("DIR")
+            let targets = match targets(&config, matches.value_of_os("DIR")) {
+                Some(targets) => targets,
+                None => return Outcome::Unreadable,
+            };
+            let format = matches.value_of("format")
+                .unwrap()
+                .parse()
                 .unwrap();
+            let policy = BackupPolicy {
+                allow_dirty: matches.values_of("allow-dirty")
+                    .map(|values| values.map(|v| v.parse().unwrap()).collect())
+                    .unwrap_or_default(),
+                require_off_device: matches.is_present("require-off-device"),
+                max_fetch_age: matches.value_of("max-fetch-age")
+                    .map(|age| parse_age(age).unwrap()),
+            };
 
-            match backup_check_dir(dir) {
-                Ok(true) => {
-                    println!("Determined repo to be clean: {:?}", dir);
-                },
-                Ok(false) => {},
-                Err(-99) => {
-                    backup_check_all(dir)?;
-                },
-                Err(err) => return Err(err),
+            let index_path = config.index_path(&config_path);
+            let mut index = load_index(&index_path);
+
+            let mut printer = ReportPrinter::new(format, print_backup_text);
+            let options = StatusOptions {
+                verify_remotes: matches.is_present("verify"),
+                deep: matches.is_present("deep"),
+                ..StatusOptions::default()
+            };
+            let sweep = sweep(&targets, matches, &options, &mut index, &mut printer, |path, alias, status| {
+                let verdict = status.map(|status| check_backup(&status, &policy));
+                let outcome = Outcome::of_verdict(&verdict);
+                let mut report = BackupReport::new(path, verdict);
+                report.alias = alias.map(str::to_string);
+                (report, outcome)
+            });
+            printer.finish();
+
+            let (mut outcome, index_changed) = sweep;
+            if index_changed {
+                outcome = outcome.max(save_index(&index, &index_path));
             }
+            outcome
         },
         (_, _) => unreachable!(),
     }
-
-    Ok(())
 }
 
-fn backup_check_all(dir: &OsStr) -> Result<(), i32> {
-    let dirs = std::fs::read_dir(dir).expect("failed to read dir");
-    for dir in dirs {
-        let dir = dir.expect("failed to read dir info");
-        match backup_check_dir(dir.path().as_os_str()) {
-            Ok(true) => {
-                println!("Determined repo to be clean: {:?}", dir);
-            },
-            Ok(false) => {},
-            Err(-99) => {
-                println!("Not a git repo: {:?}", dir);
-            },
-            Err(err) => return Err(err),
-        }
+/// Save the configuration if a change to it succeeded, otherwise report why it failed.
+///
+/// A change fails because of its arguments, unless a directory couldn't be read.
+fn save_config(config: &Config, config_path: &Path, change: Result<(), Error>) -> Outcome {
+    match change {
+        Ok(()) => {},
+        Err(err @ Error::Io(..)) => {
+            eprintln!("{}", err);
+            return Outcome::InternalError;
+        },
+        Err(err) => {
+            eprintln!("{}", err);
+            return Outcome::InvalidUsage;
+        },
+    }
+
+    if let Err(err) = config.save(config_path) {
+        eprintln!("Failed to save configuration: {}", err);
+        return Outcome::InternalError;
     }
 
-    Ok(())
+    Outcome::BackedUp
 }
 
-fn backup_check_dir(dir: &OsStr) -> Result<bool, i32> {
-    let mut repo = match Repository::open(dir.clone()) {
-        Ok(repo) => repo,
-        Err(_) => {
-            // TODO: make gud... i really want this done quick...
-            return Err(-99);
-        }
+fn sweep_args() -> [Arg<'static, 'static>; 6] {
+    [
+        Arg::with_name("max-depth")
+            .long("max-depth")
+            .takes_value(true)
+            .validator(|v| v.parse::<usize>().map(|_| ()).map_err(|err| err.to_string()))
+            .help("how many directories below DIR to search for repos [default: 5]"),
+        Arg::with_name("follow-symlinks")
+            .long("follow-symlinks")
+            .help("follow symlinks to directories while searching for repos"),
+        Arg::with_name("skip")
+            .long("skip")
+            .takes_value(true)
+            .multiple(true)
+            .use_delimiter(true)
+            .help("extra directory names to skip, on top of build and dependency directories"),
+        Arg::with_name("nested")
+            .long("nested")
+            .help("keep searching inside repos for nested repos"),
+        Arg::with_name("jobs")
+            .long("jobs")
+            .short("j")
+            .takes_value(true)
+            .validator(|v| v.parse::<usize>().map(|_| ()).map_err(|err| err.to_string()))
+            .help("how many repos to check at once [default: number of CPUs]"),
+        Arg::with_name("refresh")
+            .long("refresh")
+            .help("read every repo again, even if it hasn't changed since it was indexed"),
+    ]
+}
+
+/// Parse an age like `90m`, `12h`, `30d` or `2w` into seconds. A bare number is in days.
+fn parse_age(age: &str) -> Result<u64, String> {
+    let split = age.find(|c: char| !c.is_ascii_digit()).unwrap_or(age.len());
+    let (number, unit) = age.split_at(split);
+    let number: u64 = number.parse()
+        .map_err(|_| format!("invalid age: {}", age))?;
+    let unit = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "" | "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return Err(format!("unknown unit in age {}, expected s, m, h, d or w", age)),
     };
+    Ok(number * unit)
+}
+
+fn verify_arg() -> Arg<'static, 'static> {
+    Arg::with_name("verify")
+        .long("verify")
+        .help("ask every remote for its refs instead of trusting the last fetch")
+}
 
-    let status = get_status(&mut repo)
-        .expect("Failed to get repo status");
+fn deep_arg() -> Arg<'static, 'static> {
+    Arg::with_name("deep")
+        .long("deep")
+        .help("also look for commits that only the reflogs still refer to")
+}
 
-    if status.bare {
-        println!("Did not check bare repo");
-        return Ok(false);
+fn jobs(matches: &ArgMatches) -> usize {
+    matches.value_of("jobs")
+        .map(|jobs| jobs.parse().unwrap())
+        .unwrap_or(0)
+}
+
+fn discover_options(matches: &ArgMatches) -> DiscoverOptions {
+    let mut options = DiscoverOptions::default();
+    if let Some(depth) = matches.value_of("max-depth") {
+        options.max_depth = Some(depth.parse().unwrap());
+    }
+    options.follow_symlinks = matches.is_present("follow-symlinks");
+    if let Some(skip) = matches.values_of("skip") {
+        options.skip_dirs.extend(skip.map(|dir| dir.to_string()));
     }
+    options.descend_into_repos = matches.is_present("nested");
+    options
+}
+
+fn format_arg() -> Arg<'static, 'static> {
+    Arg::with_name("format")
+        .long("format")
+        .takes_value(true)
+        .possible_values(&["text", "json", "ndjson"])
+        .default_value("text")
+        .help("output format; see the `report` module docs for the JSON schema")
+}
 
-    if !status.clean_status {
-        println!("Repo has modified/untracked files: {:?}", dir);
-        return Ok(false);
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Format {
+    Text,
+    Json,
+    Ndjson,
+}
+
+impl std::str::FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Format, String> {
+        match s {
+            "text" => Ok(Format::Text),
+            "json" => Ok(Format::Json),
+            "ndjson" => Ok(Format::Ndjson),
+            _ => Err(format!("unknown format: {}", s)),
+        }
     }
+}
 
-    if !status.clean_state {
-        println!("Repo has a merge/rebase in progress: {:?}", dir);
-        return Ok(false);
+/// Prints reports in the requested format.
+///
+/// JSON reports are collected and printed as one array by `finish`, everything else is printed
+/// immediately.
+struct ReportPrinter<T> {
+    format: Format,
+    print_text: fn(&T),
+    reports: Vec<T>,
+}
+
+impl<T: Serialize> ReportPrinter<T> {
+    fn new(format: Format, print_text: fn(&T)) -> Self {
+        ReportPrinter {
+            format,
+            print_text,
+            reports: Vec::new(),
+        }
     }
 
-    if status.remotes.len() == 0 {
-        println!("Repo has no remotes! {:?}", dir);
-        return Ok(false);
+    fn print(&mut self, report: T) {
+        match self.format {
+            Format::Text => (self.print_text)(&report),
+            Format::Json => self.reports.push(report),
+            Format::Ndjson => {
+                let line = serde_json::to_string(&report)
+                    .expect("Failed to serialize report");
+                println!("{}", line);
+            },
+        }
     }
 
-    let mut clean = true;
+    /// Print a heading above a group of reports, only for text output.
+    fn heading(&self, heading: &str) {
+        if self.format == Format::Text {
+            println!("== {} ==", heading);
+        }
+    }
 
-    // check all the branches are up to date tracking branches or merged local branches
-    for (_name, status) in &status.branches {
-        match status {
-            BranchStatus::LocalBranch { merged_in_remote: true } => {},
-            BranchStatus::TrackingBranch(TrackingStatus::Behind | TrackingStatus::Current) => {},
-            _ => {
-                clean = false;
-            },
+    fn finish(self) {
+        if self.format == Format::Json {
+            let json = serde_json::to_string_pretty(&self.reports)
+                .expect("Failed to serialize reports");
+            println!("{}", json);
         }
     }
+}
 
-    if !clean {
-        println!("Repo has branches that are not backed up: {:?}", dir);
+fn print_status_text(report: &StatusReport) {
+    match (&report.status, &report.error) {
+        (Some(status), _) => println!("Got repo status for {}: {:?}", &report.path, status),
+        (None, error) => eprintln!("Failed to get repo status for {}: {}",
+            &report.path, error.as_deref().unwrap_or_default()),
+    }
+}
+
+fn print_backup_text(report: &BackupReport) {
+    let verdict = match (&report.verdict, &report.error) {
+        (Some(verdict), _) => verdict,
+        (None, error) => {
+            println!("Failed to check {}: {}", &report.path, error.as_deref().unwrap_or_default());
+            return;
+        },
+    };
+
+    if verdict.backed_up {
+        println!("Determined repo to be clean: {}", &report.path);
+    } else {
+        println!("Repo is not backed up: {}", &report.path);
+    }
+    for problem in verdict.problems.iter().chain(&verdict.warnings) {
+        println!("\t{}", problem);
     }
+}
+
+/// A directory to look for repos in, and the alias it was starred under if any.
+struct Target {
+    alias: Option<String>,
+    dir: PathBuf,
+}
+
+/// Resolve DIR to the directories a command should look at.
+///
+/// DIR may be a path or the alias of a starred directory; an existing path takes precedence. When
+/// DIR is omitted every starred directory is used. Returns `None` if there is nothing to look at.
+fn targets(config: &Config, dir: Option<&OsStr>) -> Option<Vec<Target>> {
+    let dir = match dir {
+        Some(dir) => dir,
+        None => {
+            let starred = config.starred();
+            if starred.is_empty() {
+                eprintln!("No DIR given and there are no starred directories to use instead");
+                return None;
+            }
+
+            return Some(starred.into_iter()
+                .map(|(alias, dir)| Target {
+                    alias: Some(alias.to_string()),
+                    dir: dir.to_path_buf(),
+                })
+                .collect());
+        },
+    };
+
+    let path = Path::new(dir);
+    let starred = dir.to_str()
+        .and_then(|alias| config.starred_path(alias).map(|path| (alias, path)));
+    let target = match starred {
+        Some((alias, starred)) if !path.exists() => Target {
+            alias: Some(alias.to_string()),
+            dir: starred.to_path_buf(),
+        },
+        _ => Target {
+            alias: None,
+            dir: path.to_path_buf(),
+        },
+    };
+
+    Some(vec![target])
+}
+
+/// Collect the status of every repo below each target, turning each into a report.
+///
+/// Returns the worst outcome of all the reports.
+fn sweep<T, F>(
+    targets: &[Target],
+    matches: &ArgMatches,
+    options: &StatusOptions,
+    index: &mut Index,
+    printer: &mut ReportPrinter<T>,
+    mut report: F,
+) -> (Outcome, bool)
+where
+    T: Serialize,
+    F: FnMut(&Path, Option<&str>, Result<RepoStatus, Error>) -> (T, Outcome),
+{
+    let discover_options = discover_options(matches);
+    // repos that haven't changed since they were indexed reuse their indexed status
+    let cache = Arc::new(if matches.is_present("refresh") {
+        Index::default()
+    } else {
+        index.clone()
+    });
 
-    if status.stashes > 0 {
-        if clean {
-            println!("Repo {:?}:", dir);
+    let mut outcome = Outcome::BackedUp;
+    let mut index_changed = false;
+    for target in targets {
+        let alias = target.alias.as_deref();
+        if let Some(alias) = alias {
+            printer.heading(&format!("{} ({})", alias, target.dir.display()));
+        }
+
+        let found = discover_repos(&target.dir, &discover_options);
+        // a target with nothing in it to check is as much a mistake as a path that isn't a repo
+        if found.is_empty() {
+            let (r, o) = report(&target.dir, alias, Err(Error::NotARepository(target.dir.clone())));
+            outcome = outcome.max(o);
+            printer.print(r);
+            continue;
+        }
+
+        let mut repos = Vec::new();
+        for found in found {
+            match found {
+                Ok(path) => repos.push(path),
+                Err(err) => {
+                    let path = match &err {
+                        Error::Io(path, _) | Error::NotARepository(path) => path.clone(),
+                        _ => target.dir.clone(),
+                    };
+                    let (r, o) = report(&path, alias, Err(err));
+                    outcome = outcome.max(o);
+                    printer.print(r);
+                },
+            }
+        }
+
+        let cache = Arc::clone(&cache);
+        let read_options = options.clone();
+        let results = map_repos(repos, jobs(matches), move |path| {
+            index::read_repo(path, None, &read_options, cache.get(path))
+        });
+        for (path, read) in results {
+            let status = read.map(|repo| {
+                let status = repo.status.clone()
+                    .expect("read_repo always returns a status");
+                if index::is_cacheable(options) {
+                    index_changed |= index.update(repo);
+                }
+                status
+            });
+
+            let (r, o) = report(&path, alias, status);
+            outcome = outcome.max(o);
+            printer.print(r);
         }
-        println!("\tRepo has stashes: {}", status.stashes);
     }
 
-    Ok(clean)
+    (outcome, index_changed)
+}
+
+/// Read the saved index, or use an empty one if it can't be read.
+fn load_index(path: &Path) -> Index {
+    Index::load(path).unwrap_or_else(|err| {
+        eprintln!("Ignoring the repo index, it could not be read: {}", err);
+        Index::default()
+    })
+}
+
+fn save_index(index: &Index, path: &Path) -> Outcome {
+    match index.save(path) {
+        Ok(()) => Outcome::BackedUp,
+        Err(err) => {
+            eprintln!("Failed to save the repo index: {}", err);
+            Outcome::InternalError
+        },
+    }
 }
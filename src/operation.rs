@@ -0,0 +1,108 @@
+//! Working out which git command a repo is in the middle of, from the files it leaves in the
+//! git dir.
+
+use crate::{Error, Operation, OperationKind, Progress};
+
+use git2::{Repository, RepositoryState};
+
+use std::fs;
+use std::path::Path;
+
+/// The operation in progress in `repo`, or `None` if there is nothing to finish.
+pub(crate) fn current_operation(repo: &Repository) -> Result<Option<Operation>, Error> {
+    let git_dir = repo.path();
+    // the merge backend (the default) keeps its state in rebase-merge, while `git am` and the
+    // apply backend use rebase-apply
+    let rebase_merge = git_dir.join("rebase-merge");
+    let rebase_apply = git_dir.join("rebase-apply");
+
+    let (kind, state_dir) = match repo.state() {
+        RepositoryState::Clean => return Ok(None),
+        RepositoryState::Merge => (OperationKind::Merge, None),
+        RepositoryState::Revert | RepositoryState::RevertSequence => (OperationKind::Revert, None),
+        RepositoryState::CherryPick | RepositoryState::CherryPickSequence =>
+            (OperationKind::CherryPick, None),
+        RepositoryState::Bisect => (OperationKind::Bisect, None),
+        RepositoryState::RebaseMerge => (OperationKind::Rebase, Some(&rebase_merge)),
+        RepositoryState::RebaseInteractive =>
+            (OperationKind::InteractiveRebase, Some(&rebase_merge)),
+        RepositoryState::Rebase => (OperationKind::Rebase, Some(&rebase_apply)),
+        RepositoryState::ApplyMailbox => (OperationKind::ApplyMailbox, Some(&rebase_apply)),
+        // rebase-apply without a marker saying which command created it; only a rebase records
+        // the branch it is rebasing
+        RepositoryState::ApplyMailboxOrRebase => if rebase_apply.join("head-name").is_file() {
+            (OperationKind::Rebase, Some(&rebase_apply))
+        } else {
+            (OperationKind::ApplyMailbox, Some(&rebase_apply))
+        },
+    };
+
+    // HEAD is detached while rebasing or bisecting, so the branch is read from the state files
+    let branch = match (kind, state_dir) {
+        // BISECT_START holds the commit bisecting started from if HEAD was already detached
+        (OperationKind::Bisect, _) => read_state(&git_dir.join("BISECT_START"))
+            .filter(|start| repo.find_branch(start, git2::BranchType::Local).is_ok()),
+        (OperationKind::ApplyMailbox, _) | (_, None) => head_branch(repo)?,
+        (_, Some(dir)) => read_state(&dir.join("head-name"))
+            .and_then(|name| name.strip_prefix("refs/heads/").map(str::to_string)),
+    };
+
+    let onto = match kind {
+        OperationKind::ApplyMailbox => None,
+        _ => state_dir.and_then(|dir| read_state(&dir.join("onto"))),
+    };
+
+    let progress = state_dir.and_then(|dir| {
+        let (step, total) = if dir == &rebase_merge {
+            ("msgnum", "end")
+        } else {
+            ("next", "last")
+        };
+        Some(Progress {
+            step: read_state(&dir.join(step))?.parse().ok()?,
+            total: read_state(&dir.join(total))?.parse().ok()?,
+        })
+    });
+
+    Ok(Some(Operation {
+        kind,
+        branch,
+        onto,
+        progress,
+        conflicted: conflicted_paths(repo)?,
+    }))
+}
+
+/// The branch HEAD points at, or `None` if it is detached.
+fn head_branch(repo: &Repository) -> Result<Option<String>, Error> {
+    let head = repo.find_reference("HEAD")?;
+    Ok(head.symbolic_target()
+        .and_then(|target| target.strip_prefix("refs/heads/"))
+        .map(str::to_string))
+}
+
+/// Contents of a one line state file, or `None` if it is missing.
+fn read_state(path: &Path) -> Option<String> {
+    fs::read_to_string(path).ok().map(|contents| contents.trim().to_string())
+}
+
+/// Paths of the index entries that have conflicts.
+fn conflicted_paths(repo: &Repository) -> Result<Vec<String>, Error> {
+    // a bare repo has no index to have conflicts in
+    if repo.is_bare() {
+        return Ok(Vec::new());
+    }
+
+    let mut paths = Vec::new();
+    for conflict in repo.index()?.conflicts()? {
+        let conflict = conflict?;
+        let entry = conflict.our.as_ref()
+            .or(conflict.their.as_ref())
+            .or(conflict.ancestor.as_ref());
+        if let Some(entry) = entry {
+            paths.push(String::from_utf8_lossy(&entry.path).into_owned());
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
@@ -0,0 +1,170 @@
+//! Detection of local branches whose changes reached a remote through a rebase or squash merge,
+//! by comparing patch ids the way `git cherry` does.
+
+use crate::{Equivalence, Error};
+
+use git2::{Diff, DiffFormat, Oid, Repository};
+
+use std::collections::{HashMap, HashSet};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::Hasher;
+
+/// Patch ids are expensive to compute, so they are shared between every branch of a repo.
+#[derive(Default)]
+pub(crate) struct PatchIdCache {
+    ids: HashMap<Oid, Option<u64>>,
+}
+
+impl PatchIdCache {
+    /// Patch id of the changes introduced by `commit`.
+    ///
+    /// Merge commits and commits that change nothing have no patch id.
+    fn commit(&mut self, repo: &Repository, commit: Oid) -> Result<Option<u64>, Error> {
+        if let Some(id) = self.ids.get(&commit) {
+            return Ok(*id);
+        }
+
+        let commit_obj = repo.find_commit(commit)?;
+        let id = match commit_obj.parent_count() {
+            0 => diff_patch_id(&repo.diff_tree_to_tree(None, Some(&commit_obj.tree()?), None)?)?,
+            1 => {
+                let parent_tree = commit_obj.parent(0)?.tree()?;
+                let diff = repo.diff_tree_to_tree(Some(&parent_tree), Some(&commit_obj.tree()?), None)?;
+                diff_patch_id(&diff)?
+            },
+            _ => None,
+        };
+
+        self.ids.insert(commit, id);
+        Ok(id)
+    }
+}
+
+/// Check if the changes on `branch` exist in the remote history under different commits.
+///
+/// Returns `Equivalence::Rebased` if every commit unique to the branch has a remote commit with the
+/// same patch id, or `Equivalence::Squashed` if a single remote commit has the same patch id as the
+/// whole branch relative to where it forked from a remote branch.
+///
+/// Only remote commits since the branch forked are compared, so remote branches that share no
+/// history with it are skipped rather than having their whole history read on every status.
+pub(crate) fn find_equivalent(
+    repo: &Repository,
+    cache: &mut PatchIdCache,
+    branch: Oid,
+    remote_tips: &[Oid],
+) -> Result<Option<Equivalence>, Error> {
+    // where the branch forked from each remote branch it shares history with
+    let mut related = Vec::new();
+    let mut bases = Vec::new();
+    for &tip in remote_tips {
+        let base = match crate::merge_base(repo, branch, tip) {
+            Ok(base) => base,
+            Err(Error::UnrelatedHistories { .. }) => continue,
+            Err(err) => return Err(err),
+        };
+        related.push(tip);
+        if !bases.contains(&base) {
+            bases.push(base);
+        }
+    }
+    if related.is_empty() {
+        return Ok(None);
+    }
+
+    // patch ids of every remote commit that isn't already part of the branch, which stops at the
+    // merge bases
+    let mut remote_ids = HashSet::new();
+    let mut walk = repo.revwalk()?;
+    for &tip in &related {
+        walk.push(tip)?;
+    }
+    walk.hide(branch)?;
+    for commit in walk {
+        if let Some(id) = cache.commit(repo, commit?)? {
+            remote_ids.insert(id);
+        }
+    }
+
+    if remote_ids.is_empty() {
+        return Ok(None);
+    }
+
+    // rebased or cherry-picked: every commit unique to the branch has a remote copy
+    let mut walk = repo.revwalk()?;
+    walk.push(branch)?;
+    for &tip in &related {
+        walk.hide(tip)?;
+    }
+    let mut unique = 0;
+    let mut all_found = true;
+    for commit in walk {
+        // merges don't carry changes of their own, so they have no id and are skipped
+        if let Some(id) = cache.commit(repo, commit?)? {
+            unique += 1;
+            if !remote_ids.contains(&id) {
+                all_found = false;
+                break;
+            }
+        }
+    }
+    if unique > 0 && all_found {
+        return Ok(Some(Equivalence::Rebased));
+    }
+
+    // squashed: the combined changes since the fork point were applied as one remote commit
+    let branch_tree = repo.find_commit(branch)?.tree()?;
+    for base in bases {
+        let base_tree = repo.find_commit(base)?.tree()?;
+        let diff = repo.diff_tree_to_tree(Some(&base_tree), Some(&branch_tree), None)?;
+        if let Some(id) = diff_patch_id(&diff)? {
+            if remote_ids.contains(&id) {
+                return Ok(Some(Equivalence::Squashed));
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+/// Hash a diff while ignoring whitespace, line numbers and the blob ids of text files, like
+/// `git patch-id`.
+///
+/// Returns `None` for an empty diff.
+fn diff_patch_id(diff: &Diff) -> Result<Option<u64>, Error> {
+    if diff.deltas().len() == 0 {
+        return Ok(None);
+    }
+
+    let mut hasher = DefaultHasher::new();
+    diff.print(DiffFormat::Patch, |delta, _hunk, line| {
+        match line.origin() {
+            // the file header has the paths and modes, but its index line has the blob ids
+            'F' => {
+                for header in line.content().split(|&byte| byte == b'\n') {
+                    if !header.starts_with(b"index ") {
+                        hasher.write(header);
+                    }
+                }
+            },
+            // there are no lines to compare for binary files, so their contents are told apart by
+            // blob id, as `git patch-id` does
+            'B' => {
+                hasher.write(delta.old_file().id().as_bytes());
+                hasher.write(delta.new_file().id().as_bytes());
+            },
+            origin @ ('+' | '-' | ' ') => {
+                hasher.write_u8(origin as u8);
+                for &byte in line.content() {
+                    if !byte.is_ascii_whitespace() {
+                        hasher.write_u8(byte);
+                    }
+                }
+            },
+            _ => {},
+        }
+        true
+    })?;
+
+    Ok(Some(hasher.finish()))
+}
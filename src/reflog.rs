@@ -0,0 +1,56 @@
+//! Finding commits that are only kept alive by reflogs.
+
+use crate::{Error, ReflogCommit};
+
+use git2::{Repository, Sort};
+
+/// Every commit reachable from an entry in the reflog of HEAD or of a local branch, but not from
+/// any ref, newest first.
+pub(crate) fn reflog_commits(repo: &Repository) -> Result<Vec<ReflogCommit>, Error> {
+    let mut names = vec!["HEAD".to_string()];
+    for reference in repo.references_glob("refs/heads/*")? {
+        if let Some(name) = reference?.name() {
+            names.push(name.to_string());
+        }
+    }
+
+    let mut walk = repo.revwalk()?;
+    walk.set_sorting(Sort::TIME);
+    let mut pushed = false;
+    for name in &names {
+        let reflog = repo.reflog(name)?;
+        for entry in reflog.iter() {
+            for id in &[entry.id_old(), entry.id_new()] {
+                // the first entry starts from zero, and `git gc` may already have pruned the
+                // commits of expired entries
+                if id.is_zero() || repo.find_commit(*id).is_err() {
+                    continue;
+                }
+                walk.push(*id)?;
+                pushed = true;
+            }
+        }
+    }
+    if !pushed {
+        return Ok(Vec::new());
+    }
+
+    // anything a ref can reach is accounted for by the branch, tag and stash checks
+    walk.hide_glob("refs/*")?;
+    if let Ok(head) = repo.head() {
+        if let Some(head) = head.target() {
+            walk.hide(head)?;
+        }
+    }
+
+    walk.map(|id| -> Result<ReflogCommit, Error> {
+            let commit = repo.find_commit(id?)?;
+            let summary = commit.summary_bytes().unwrap_or_default();
+            Ok(ReflogCommit {
+                id: commit.id().to_string(),
+                summary: String::from_utf8_lossy(summary).into_owned(),
+                time: commit.time().seconds(),
+            })
+        })
+        .collect()
+}
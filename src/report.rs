@@ -0,0 +1,84 @@
+//! Machine-readable reports produced by the `status` and `backupcheck` commands.
+//!
+//! Every report is a JSON object with these fields:
+//!
+//! - `schema_version`: always `SCHEMA_VERSION`. It is incremented whenever a field is removed or
+//!   changes meaning; adding fields does not change the version.
+//! - `path`: the repo path as given on the command line or found while scanning a directory.
+//! - `alias`: present only if the repo was found in a starred directory, with that directory's
+//!   alias.
+//! - `error`: present only if the repo could not be checked, with a human readable message.
+//!
+//! A `StatusReport` adds `status`, the serialized `RepoStatus`. A `BackupReport` adds `verdict`,
+//! the serialized `BackupVerdict`. Both are omitted when `error` is present.
+//!
+//! The `json` output format prints a single array of reports, while `ndjson` prints one report per
+//! line as soon as it is available.
+
+use crate::{Error, RepoStatus};
+use crate::backup::BackupVerdict;
+
+use serde::{Serialize, Deserialize};
+
+use std::path::Path;
+
+pub const SCHEMA_VERSION: u32 = 3;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all="snake_case")]
+pub struct StatusReport {
+    pub schema_version: u32,
+    pub path: String,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub alias: Option<String>,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub status: Option<RepoStatus>,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl StatusReport {
+    pub fn new<P: AsRef<Path>>(path: P, status: Result<RepoStatus, Error>) -> StatusReport {
+        let (status, error) = split_result(status);
+        StatusReport {
+            schema_version: SCHEMA_VERSION,
+            path: path.as_ref().to_string_lossy().into_owned(),
+            alias: None,
+            status,
+            error,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all="snake_case")]
+pub struct BackupReport {
+    pub schema_version: u32,
+    pub path: String,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub alias: Option<String>,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub verdict: Option<BackupVerdict>,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl BackupReport {
+    pub fn new<P: AsRef<Path>>(path: P, verdict: Result<BackupVerdict, Error>) -> BackupReport {
+        let (verdict, error) = split_result(verdict);
+        BackupReport {
+            schema_version: SCHEMA_VERSION,
+            path: path.as_ref().to_string_lossy().into_owned(),
+            alias: None,
+            verdict,
+            error,
+        }
+    }
+}
+
+fn split_result<T>(result: Result<T, Error>) -> (Option<T>, Option<String>) {
+    match result {
+        Ok(value) => (Some(value), None),
+        Err(err) => (None, Some(err.to_string())),
+    }
+}
@@ -0,0 +1,128 @@
+//! Stashes are never pushed, so they are listed in detail and can be turned into something that
+//! can be: branches, or a bundle file.
+
+use crate::{Error, Stash};
+
+use git2::{Oid, Repository};
+
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+/// Every stash in the repo, newest first, as in `git stash list`.
+pub fn list_stashes(repo: &mut Repository) -> Result<Vec<Stash>, Error> {
+    let mut found = Vec::new();
+    repo.stash_foreach(|index, message, id| {
+        found.push((index, message.to_string(), *id));
+        true
+    })?;
+
+    found.into_iter()
+        .map(|(index, message, id)| {
+            let commit = repo.find_commit(id)?;
+            Ok(Stash {
+                index,
+                id: id.to_string(),
+                branch: stash_branch(&message),
+                message,
+                time: commit.time().seconds(),
+                files: stash_files(repo, &commit)?,
+            })
+        })
+        .collect()
+}
+
+/// Name of the branch a stash was made on, from messages like `WIP on master: ...` or
+/// `On master: ...`.
+fn stash_branch(message: &str) -> Option<String> {
+    let rest = message.strip_prefix("WIP on ")
+        .or_else(|| message.strip_prefix("On "))?;
+    let branch = &rest[..rest.find(": ")?];
+    if branch == "(no branch)" {
+        None
+    } else {
+        Some(branch.to_string())
+    }
+}
+
+/// Count the files a stash saves changes to, including untracked files.
+fn stash_files(repo: &Repository, stash: &git2::Commit) -> Result<usize, Error> {
+    // the stash commit holds the working tree, with HEAD as its first parent and the untracked
+    // files, if they were saved, as a parentless third parent
+    let head = stash.parent(0)?;
+    let mut files = repo.diff_tree_to_tree(Some(&head.tree()?), Some(&stash.tree()?), None)?
+        .deltas()
+        .len();
+    if let Ok(untracked) = stash.parent(2) {
+        files += repo.diff_tree_to_tree(None, Some(&untracked.tree()?), None)?
+            .deltas()
+            .len();
+    }
+
+    Ok(files)
+}
+
+/// The branch name a stash is exported as, `<prefix><short id>`.
+///
+/// The id is used rather than the index, since indexes change whenever a stash is added or
+/// dropped.
+pub fn export_name(prefix: &str, stash: &Stash) -> String {
+    format!("{}{}", prefix, &stash.id[..stash.id.len().min(10)])
+}
+
+/// Create a branch pointing at every stash, so they can be pushed.
+///
+/// The branches hold the stash commits themselves, so a stash can be restored with
+/// `git stash apply <branch>`. Returns the names of the branches, skipping ones that exist.
+pub fn stashes_to_branches(repo: &mut Repository, prefix: &str) -> Result<Vec<String>, Error> {
+    let mut created = Vec::new();
+    for stash in list_stashes(repo)? {
+        let name = export_name(prefix, &stash);
+        let refname = format!("refs/heads/{}", name);
+        if repo.find_reference(&refname).is_ok() {
+            continue;
+        }
+
+        let message = format!("vrh: export stash@{{{}}}", stash.index);
+        repo.reference(&refname, Oid::from_str(&stash.id)?, false, &message)?;
+        created.push(name);
+    }
+
+    Ok(created)
+}
+
+/// Write every stash to a bundle at `path`, with branch names as in `stashes_to_branches`.
+///
+/// The bundle holds the whole history of each stash, so it can be restored without the original
+/// repo, e.g. with `git fetch <bundle> 'refs/heads/*:refs/heads/*'`. Returns the bundled branch
+/// names.
+pub fn stashes_to_bundle(
+    repo: &mut Repository,
+    prefix: &str,
+    path: &Path,
+) -> Result<Vec<String>, Error> {
+    let stashes = list_stashes(repo)?;
+
+    let mut walk = repo.revwalk()?;
+    let mut header = String::from("# v2 git bundle\n");
+    let mut names = Vec::new();
+    for stash in &stashes {
+        let name = export_name(prefix, stash);
+        walk.push(Oid::from_str(&stash.id)?)?;
+        header.push_str(&format!("{} refs/heads/{}\n", stash.id, name));
+        names.push(name);
+    }
+    header.push('\n');
+
+    let mut pack = repo.packbuilder()?;
+    pack.insert_walk(&mut walk)?;
+    let mut buf = git2::Buf::new();
+    pack.write_buf(&mut buf)?;
+
+    let io_err = |err| Error::Io(path.to_path_buf(), err);
+    let mut file = fs::File::create(path).map_err(io_err)?;
+    file.write_all(header.as_bytes()).map_err(io_err)?;
+    file.write_all(&buf).map_err(io_err)?;
+
+    Ok(names)
+}
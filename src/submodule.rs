@@ -0,0 +1,71 @@
+//! Checking submodules, which are repos of their own with their own work to lose.
+
+use crate::{contained_in_any, get_status_with_options, Error, StatusOptions, SubmoduleStatus};
+
+use git2::Repository;
+
+/// The status of every submodule in the working tree of `repo`, sorted by path.
+///
+/// A submodule that can't be read is reported in its `SubmoduleStatus` rather than as an error.
+pub(crate) fn submodule_statuses(
+    repo: &Repository,
+    options: &StatusOptions,
+) -> Result<Vec<SubmoduleStatus>, Error> {
+    let workdir = match repo.workdir() {
+        Some(workdir) => workdir,
+        None => return Ok(Vec::new()),
+    };
+
+    let mut statuses = Vec::new();
+    for submodule in repo.submodules()? {
+        let path = submodule.path();
+        let mut status = SubmoduleStatus {
+            name: String::from_utf8_lossy(submodule.name_bytes()).into_owned(),
+            path: path.to_string_lossy().into_owned(),
+            url: submodule.url().map(str::to_string),
+            recorded: submodule.head_id().map(|id| id.to_string()),
+            recorded_in_remote: None,
+            status: None,
+            error: None,
+        };
+
+        // an uninitialized submodule is an empty directory with nothing in it to lose
+        if workdir.join(path).join(".git").exists() {
+            let result = submodule.open()
+                .map_err(Error::from)
+                .and_then(|mut sub| {
+                    let recorded_in_remote = match submodule.head_id() {
+                        Some(recorded) => recorded_in_remote(&sub, recorded)?,
+                        None => None,
+                    };
+                    let sub_status = get_status_with_options(&mut sub, options)?;
+                    Ok((recorded_in_remote, sub_status))
+                });
+            match result {
+                Ok((recorded_in_remote, sub_status)) => {
+                    status.recorded_in_remote = recorded_in_remote;
+                    status.status = Some(Box::new(sub_status));
+                },
+                Err(err) => status.error = Some(err.to_string()),
+            }
+        }
+
+        statuses.push(status);
+    }
+
+    statuses.sort_by(|one, two| one.path.cmp(&two.path));
+    Ok(statuses)
+}
+
+/// True if the submodule `sub` has `recorded` in one of its remote branches, or `None` if it
+/// doesn't have the commit at all.
+fn recorded_in_remote(sub: &Repository, recorded: git2::Oid) -> Result<Option<bool>, Error> {
+    if sub.find_commit(recorded).is_err() {
+        return Ok(None);
+    }
+
+    let remote_tips = sub.branches(Some(git2::BranchType::Remote))?
+        .map(|branch| Ok(branch?.0.get().peel_to_commit()?.id()))
+        .collect::<Result<Vec<_>, Error>>()?;
+    contained_in_any(sub, recorded, &remote_tips).map(Some)
+}
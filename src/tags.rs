@@ -0,0 +1,97 @@
+//! Finding tags that were made locally and never pushed.
+
+use crate::{Error, UnpushedTag};
+use crate::verify::{map_pattern, parse_refspec};
+
+use git2::{Oid, Repository};
+
+use std::collections::HashSet;
+
+/// Every tag that isn't contained in one of `remote_tips`, or that the remote-tracking refs of
+/// one of `remotes` show is missing or different on that remote.
+pub(crate) fn unpushed_tags(
+    repo: &Repository,
+    remotes: &[git2::Remote],
+    remote_tips: &[Oid],
+) -> Result<Vec<UnpushedTag>, Error> {
+    let mut tags = Vec::new();
+    for reference in repo.references_glob("refs/tags/*")? {
+        let reference = reference?;
+        let refname = match reference.name() {
+            Some(refname) => refname.to_string(),
+            None => {
+                let name = String::from_utf8_lossy(reference.name_bytes()).into_owned();
+                return Err(Error::NonUtf8Name(name));
+            },
+        };
+        // tags of trees or blobs can't be contained in a branch
+        let (id, commit) = match (reference.target(), reference.peel_to_commit()) {
+            (Some(id), Ok(commit)) => (id, commit.id()),
+            _ => continue,
+        };
+        tags.push((refname, id, commit));
+    }
+    if tags.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    // the tagged commits that can't be reached from any remote branch
+    let mut walk = repo.revwalk()?;
+    for (_, _, commit) in &tags {
+        walk.push(*commit)?;
+    }
+    for tip in remote_tips {
+        walk.hide(*tip)?;
+    }
+    let unmerged = walk.collect::<Result<HashSet<_>, _>>()?;
+
+    let specs = remotes.iter()
+        .map(|remote| {
+            let specs: Vec<_> = remote.fetch_refspecs()?
+                .iter()
+                .flatten()
+                .filter_map(parse_refspec)
+                .collect();
+            Ok((remote.name().unwrap_or_default(), specs))
+        })
+        .collect::<Result<Vec<_>, git2::Error>>()?;
+
+    let mut unpushed = Vec::new();
+    for (refname, id, commit) in tags {
+        let mut missing_on = Vec::new();
+        let mut differs_on = Vec::new();
+        for (remote, specs) in &specs {
+            // most remotes only fetch branches, so there is nothing to compare with, and a remote
+            // that fetches tags onto themselves, like a mirror, would only compare the tag with
+            // itself
+            let tracking = specs.iter().find_map(|(src, dst)| map_pattern(src, dst, &refname));
+            let tracking = match tracking {
+                Some(tracking) if tracking != refname => tracking,
+                _ => continue,
+            };
+            match repo.refname_to_id(&tracking) {
+                Ok(tracked) if tracked == id => {},
+                Ok(_) => differs_on.push(remote.to_string()),
+                Err(err) if err.code() == git2::ErrorCode::NotFound =>
+                    missing_on.push(remote.to_string()),
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        let merged_in_remote = !unmerged.contains(&commit);
+        if merged_in_remote && missing_on.is_empty() && differs_on.is_empty() {
+            continue;
+        }
+        unpushed.push(UnpushedTag {
+            name: refname["refs/tags/".len()..].to_string(),
+            id: id.to_string(),
+            annotated: id != commit,
+            merged_in_remote,
+            missing_on,
+            differs_on,
+        });
+    }
+
+    unpushed.sort_by(|one, two| one.name.cmp(&two.name));
+    Ok(unpushed)
+}
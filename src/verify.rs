@@ -0,0 +1,126 @@
+//! Checking remote-tracking refs against what the remote actually has, like `git ls-remote`.
+
+use crate::{RemoteVerification, StaleRef};
+
+use git2::{Cred, CredentialType, Direction, Oid, Repository};
+
+use std::collections::{BTreeMap, HashMap};
+
+/// Connect to the remote called `name` and compare its refs with the local refs they fetch into.
+///
+/// A remote that can't be reached is reported in the verification rather than as an error, so
+/// one unreachable remote doesn't hide the status of the repo.
+pub(crate) fn verify_remote(repo: &Repository, name: &str) -> RemoteVerification {
+    match stale_refs(repo, name) {
+        Ok(stale_refs) => RemoteVerification {
+            stale_refs,
+            error: None,
+        },
+        Err(err) => RemoteVerification {
+            stale_refs: Vec::new(),
+            error: Some(err.message().to_string()),
+        },
+    }
+}
+
+fn stale_refs(repo: &Repository, name: &str) -> Result<Vec<StaleRef>, git2::Error> {
+    let mut remote = repo.find_remote(name)?;
+    let specs: Vec<_> = remote.fetch_refspecs()?
+        .iter()
+        .flatten()
+        .filter_map(parse_refspec)
+        .collect();
+
+    let config = repo.config()?;
+    let mut attempts = 0;
+    let mut callbacks = git2::RemoteCallbacks::new();
+    callbacks.credentials(move |url, username, allowed| {
+        // libgit2 keeps asking as long as credentials are rejected
+        attempts += 1;
+        if attempts > 3 {
+            return Err(git2::Error::from_str("no accepted credentials"));
+        }
+
+        if allowed.contains(CredentialType::SSH_KEY) {
+            if let Some(username) = username {
+                return Cred::ssh_key_from_agent(username);
+            }
+        }
+        if allowed.contains(CredentialType::USER_PASS_PLAINTEXT) {
+            return Cred::credential_helper(&config, url, username);
+        }
+        Cred::default()
+    });
+
+    let connection = remote.connect_auth(Direction::Fetch, Some(callbacks), None)?;
+    let heads: HashMap<String, Oid> = connection.list()?
+        .iter()
+        // HEAD is a symref, and `^{}` entries are peeled tags rather than refs
+        .filter(|head| head.name() != "HEAD" && !head.name().ends_with("^{}"))
+        .map(|head| (head.name().to_string(), head.oid()))
+        .collect();
+
+    // every local ref the remote's refs are fetched into, with what the remote has for it
+    let mut expected = BTreeMap::new();
+    for (name, oid) in &heads {
+        for (src, dst) in &specs {
+            if let Some(local) = map_pattern(src, dst, name) {
+                expected.insert(local, Some(*oid));
+            }
+        }
+    }
+    // local refs whose remote ref was deleted
+    for reference in repo.references()? {
+        let reference = reference?;
+        let name = match reference.name() {
+            Some(name) if reference.symbolic_target().is_none() => name,
+            _ => continue,
+        };
+        if specs.iter().any(|(src, dst)| map_pattern(dst, src, name).is_some()) {
+            expected.entry(name.to_string()).or_insert(None);
+        }
+    }
+
+    let stale = expected.into_iter()
+        .filter_map(|(local_ref, actual)| {
+            let recorded = repo.refname_to_id(&local_ref).ok();
+            if recorded == actual {
+                return None;
+            }
+
+            Some(StaleRef {
+                local_ref,
+                recorded: recorded.map(|oid| oid.to_string()),
+                actual: actual.map(|oid| oid.to_string()),
+            })
+        })
+        .collect();
+
+    Ok(stale)
+}
+
+/// Split a refspec into its source and destination, ignoring the force flag.
+pub(crate) fn parse_refspec(spec: &str) -> Option<(String, String)> {
+    let spec = spec.strip_prefix('+').unwrap_or(spec);
+    let (src, dst) = spec.split_at(spec.find(':')?);
+    Some((src.to_string(), dst[1..].to_string()))
+}
+
+/// Map `name` from one side of a refspec to the other, where `*` matches any part of a name.
+pub(crate) fn map_pattern(from: &str, to: &str, name: &str) -> Option<String> {
+    let star = match from.find('*') {
+        Some(star) => star,
+        None => return if from == name { Some(to.to_string()) } else { None },
+    };
+
+    let (prefix, suffix) = (&from[..star], &from[star + 1..]);
+    if name.len() < prefix.len() + suffix.len()
+        || !name.starts_with(prefix)
+        || !name.ends_with(suffix)
+    {
+        return None;
+    }
+
+    let matched = &name[prefix.len()..name.len() - suffix.len()];
+    Some(to.replacen('*', matched, 1))
+}
@@ -0,0 +1,133 @@
+//! Checking linked worktrees, made with `git worktree add`, which share the repo's objects and
+//! refs but have their own HEAD, index and files.
+//!
+//! git2 has no worktree API, so they are found by reading the git dir the way `git worktree list`
+//! does.
+
+use crate::{get_file_status, head_status, operation, Error, StatusOptions, WorktreeStatus};
+
+use git2::Repository;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A linked worktree as recorded under `worktrees/` in the git dir.
+pub(crate) struct LinkedWorktree {
+    pub name: String,
+    /// The worktree's own git dir, `worktrees/<name>` in the main git dir.
+    pub git_dir: PathBuf,
+    /// The worktree's working directory, which may no longer exist.
+    pub path: PathBuf,
+}
+
+/// Every linked worktree of `repo`, sorted by name.
+///
+/// Worktrees are only listed for the repo that owns them, not for `repo` if it is itself a
+/// linked worktree.
+pub(crate) fn linked_worktrees(repo: &Repository) -> Result<Vec<LinkedWorktree>, Error> {
+    if repo.is_worktree() {
+        return Ok(Vec::new());
+    }
+
+    let dir = repo.path().join("worktrees");
+    let entries = match fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(Error::Io(dir, err)),
+    };
+
+    let mut worktrees = Vec::new();
+    for entry in entries {
+        let entry = entry.map_err(|err| Error::Io(dir.clone(), err))?;
+        let git_dir = entry.path();
+        // `gitdir` holds the path of the `.git` file in the working directory; without it the
+        // entry is left over from an interrupted `git worktree add`
+        let dot_git = match read_path(&git_dir, "gitdir") {
+            Some(dot_git) => dot_git,
+            None => continue,
+        };
+        worktrees.push(LinkedWorktree {
+            name: entry.file_name().to_string_lossy().into_owned(),
+            path: dot_git.parent().map(Path::to_path_buf).unwrap_or(dot_git),
+            git_dir,
+        });
+    }
+
+    worktrees.sort_by(|one, two| one.name.cmp(&two.name));
+    Ok(worktrees)
+}
+
+/// The HEAD, files and operation in progress of every linked worktree of `repo`, sorted by name.
+///
+/// A worktree that can't be read is reported in its `WorktreeStatus` rather than as an error.
+pub(crate) fn worktree_statuses(
+    repo: &Repository,
+    options: &StatusOptions,
+    remote_tips: &[git2::Oid],
+) -> Result<Vec<WorktreeStatus>, Error> {
+    let mut statuses = Vec::new();
+    for worktree in linked_worktrees(repo)? {
+        let mut status = WorktreeStatus {
+            name: worktree.name,
+            path: worktree.path.to_string_lossy().into_owned(),
+            locked: worktree.git_dir.join("locked").exists(),
+            missing: false,
+            head: None,
+            files: Default::default(),
+            operation: None,
+            error: None,
+        };
+
+        // a deleted working directory has no files left to lose, `git worktree prune` cleans up
+        if !worktree.path.exists() {
+            status.missing = true;
+            statuses.push(status);
+            continue;
+        }
+
+        let result = Repository::open(&worktree.path)
+            .map_err(Error::from)
+            .and_then(|worktree| Ok((
+                head_status(&worktree, remote_tips)?,
+                get_file_status(&worktree, options.include_paths)?,
+                operation::current_operation(&worktree)?,
+            )));
+        match result {
+            Ok((head, files, operation)) => {
+                status.head = Some(head);
+                status.files = files;
+                status.operation = operation;
+            },
+            Err(err) => status.error = Some(err.to_string()),
+        }
+
+        statuses.push(status);
+    }
+
+    Ok(statuses)
+}
+
+/// If `dir` is a linked worktree, the directory of the repo that owns it: its working directory,
+/// or the git dir itself for a bare repo.
+pub(crate) fn worktree_owner(dir: &Path) -> Option<PathBuf> {
+    // the `.git` of a linked worktree is a file pointing at `worktrees/<name>` in the main git
+    // dir, which records the main git dir in `commondir`; submodules have no `commondir`
+    let contents = fs::read_to_string(dir.join(".git")).ok()?;
+    let git_dir = dir.join(contents.trim().strip_prefix("gitdir:")?.trim());
+    let common_dir = crate::common_dir(&git_dir);
+    if common_dir == git_dir {
+        return None;
+    }
+    let common_dir = fs::canonicalize(common_dir).ok()?;
+    if common_dir.file_name().is_some_and(|name| name == ".git") {
+        common_dir.parent().map(Path::to_path_buf)
+    } else {
+        Some(common_dir)
+    }
+}
+
+/// The path in the one line file `name` in `dir`, resolved relative to `dir`.
+fn read_path(dir: &Path, name: &str) -> Option<PathBuf> {
+    let contents = fs::read_to_string(dir.join(name)).ok()?;
+    Some(dir.join(contents.trim()))
+}
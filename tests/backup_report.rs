@@ -0,0 +1,164 @@
+use virtual_repo_hub::{
+    BranchStatus,
+    FileStatus,
+    Head,
+    Remote,
+    RemoteDistance,
+    RemoteLocation,
+    RepoStatus,
+    Stash,
+    TrackingStatus,
+};
+use virtual_repo_hub::backup::{check_backup, BackupPolicy, BackupProblem};
+use virtual_repo_hub::report::{BackupReport, SCHEMA_VERSION};
+
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+fn stash() -> Stash {
+    Stash {
+        index: 0,
+        id: "0123456789abcdef0123456789abcdef01234567".to_string(),
+        message: "WIP on master: 0123456 fix things".to_string(),
+        branch: Some("master".to_string()),
+        time: 0,
+        files: 2,
+    }
+}
+
+fn ahead_status() -> RepoStatus {
+    let mut branches = HashMap::new();
+    branches.insert("master".to_string(), BranchStatus::TrackingBranch(TrackingStatus {
+        upstream: "origin/master".to_string(),
+        ahead: 3,
+        behind: 0,
+        unrelated: false,
+        stale: false,
+    }));
+
+    RepoStatus {
+        bare: false,
+        head: Head::Branch { name: "master".to_string() },
+        files: FileStatus {
+            untracked: 2,
+            ..FileStatus::default()
+        },
+        operation: None,
+        stashes: vec![stash()],
+        unpushed_tags: Vec::new(),
+        reflog_commits: None,
+        remotes: Vec::new(),
+        branches,
+        submodules: Vec::new(),
+        worktrees: Vec::new(),
+        lfs: None,
+    }
+}
+
+#[test]
+fn verdict_lists_problems() {
+    let verdict = check_backup(&ahead_status(), &BackupPolicy::default());
+
+    assert!(!verdict.backed_up);
+    assert_eq!(verdict.problems.len(), 3);
+    assert_eq!(verdict.problems[2], BackupProblem::UnpushedCommits {
+        branch: "master".to_string(),
+        upstream: "origin/master".to_string(),
+        ahead: 3,
+    });
+    assert_eq!(verdict.warnings, vec![BackupProblem::Stash(stash())]);
+    assert_eq!(verdict.warnings[0].to_string(), "stash@{0}: WIP on master: 0123456 fix things (2 files)");
+}
+
+#[test]
+fn policy_allows_untracked() {
+    let policy = BackupPolicy {
+        allow_dirty: vec!["untracked".parse().unwrap()],
+        ..BackupPolicy::default()
+    };
+    let verdict = check_backup(&ahead_status(), &policy);
+
+    assert!(!verdict.problems.iter()
+        .any(|problem| matches!(problem, BackupProblem::UncommittedChanges { .. })));
+}
+
+fn remote(name: &str, location: RemoteLocation, distance: RemoteDistance) -> Remote {
+    Remote {
+        name: name.to_string(),
+        url: None,
+        push_url: None,
+        fetch_refspecs: Vec::new(),
+        push_refspecs: Vec::new(),
+        location,
+        distance,
+        last_fetch: None,
+        verification: None,
+    }
+}
+
+#[test]
+fn policy_requires_off_device_remote() {
+    let mut status = RepoStatus {
+        branches: HashMap::new(),
+        stashes: Vec::new(),
+        files: FileStatus::default(),
+        ..ahead_status()
+    };
+    status.remotes.push(remote("clone", RemoteLocation::Local, RemoteDistance::SameFilesystem));
+
+    let verdict = check_backup(&status, &BackupPolicy::default());
+    assert!(verdict.backed_up);
+    assert_eq!(verdict.warnings, vec![BackupProblem::NoOffDeviceRemote]);
+
+    let policy = BackupPolicy {
+        require_off_device: true,
+        ..BackupPolicy::default()
+    };
+    let verdict = check_backup(&status, &policy);
+    assert_eq!(verdict.problems, vec![BackupProblem::NoOffDeviceRemote]);
+
+    let host = "github.com".to_string();
+    status.remotes.push(remote("origin", RemoteLocation::Forge { host }, RemoteDistance::Network));
+    let verdict = check_backup(&status, &policy);
+    assert!(verdict.backed_up);
+    assert!(verdict.warnings.is_empty());
+}
+
+#[test]
+fn policy_warns_about_old_fetches() {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
+    let mut fresh = remote("fresh", RemoteLocation::Unknown, RemoteDistance::Network);
+    fresh.last_fetch = Some(now - 60);
+    let mut old = remote("old", RemoteLocation::Unknown, RemoteDistance::Network);
+    old.last_fetch = Some(now - 3 * 86400);
+    let never = remote("never", RemoteLocation::Unknown, RemoteDistance::Network);
+
+    let status = RepoStatus {
+        remotes: vec![fresh, old, never],
+        stashes: Vec::new(),
+        ..ahead_status()
+    };
+    assert!(check_backup(&status, &BackupPolicy::default()).warnings.is_empty());
+
+    let policy = BackupPolicy {
+        max_fetch_age: Some(86400),
+        ..BackupPolicy::default()
+    };
+    let warnings = check_backup(&status, &policy).warnings;
+    assert_eq!(warnings.len(), 2);
+    assert_eq!(warnings[0].to_string(), "old: last fetched 3 days ago");
+    assert_eq!(warnings[1], BackupProblem::OldFetch { remote: "never".to_string(), age: None });
+}
+
+#[test]
+fn report_json_schema() {
+    let verdict = check_backup(&ahead_status(), &BackupPolicy::default());
+    let report = BackupReport::new("some/repo", Ok(verdict));
+
+    let json = serde_json::to_value(&report).unwrap();
+    assert_eq!(json["schema_version"], SCHEMA_VERSION);
+    assert_eq!(json["path"], "some/repo");
+    assert_eq!(json["verdict"]["problems"][0]["kind"], "uncommitted_changes");
+    assert_eq!(json["verdict"]["problems"][0]["changes"]["untracked"], 2);
+    assert!(json.get("error").is_none());
+}
@@ -0,0 +1,37 @@
+use virtual_repo_hub::{Error, StatusOptions};
+use virtual_repo_hub::batch::collect_statuses;
+
+use git2::Repository;
+use tempfile::tempdir;
+
+use std::collections::HashMap;
+
+#[test]
+fn collects_every_repo_despite_failures() {
+    let root = tempdir().unwrap();
+    let mut paths = Vec::new();
+    for i in 0..6 {
+        let path = root.path().join(i.to_string());
+        Repository::init(&path).unwrap();
+        paths.push(path);
+    }
+    let missing = root.path().join("missing");
+    paths.push(missing.clone());
+
+    let results: HashMap<_, _> = collect_statuses(paths.clone(), &StatusOptions::default(), 3)
+        .collect();
+
+    assert_eq!(results.len(), paths.len());
+    for path in &paths[..6] {
+        assert!(results[path].is_ok());
+    }
+    match &results[&missing] {
+        Err(Error::NotARepository(path)) => assert_eq!(path, &missing),
+        other => panic!("expected missing repo to fail, got {:?}", other),
+    }
+}
+
+#[test]
+fn empty_batch() {
+    assert_eq!(collect_statuses(Vec::new(), &StatusOptions::default(), 0).count(), 0);
+}
@@ -0,0 +1,28 @@
+// each test crate only uses some of these
+#![allow(dead_code)]
+
+use std::path::Path;
+use std::process::{Command, Output};
+
+fn run_git(dir: &Path, args: &[&str]) -> Output {
+    Command::new("git")
+        .args(["-c", "user.name=vrh", "-c", "user.email=vrh@example.com"])
+        // local submodule URLs are refused by default since git 2.38.1
+        .args(["-c", "protocol.file.allow=always"])
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .unwrap()
+}
+
+/// Run git in `dir`, failing the test if it fails, and return its trimmed output.
+pub fn git(dir: &Path, args: &[&str]) -> String {
+    let output = run_git(dir, args);
+    assert!(output.status.success(), "git {:?} failed", args);
+    String::from_utf8(output.stdout).unwrap().trim().to_string()
+}
+
+/// Run git in `dir` and return whether it succeeded, for commands that are expected to fail.
+pub fn try_git(dir: &Path, args: &[&str]) -> bool {
+    run_git(dir, args).status.success()
+}
@@ -0,0 +1,80 @@
+use virtual_repo_hub::discover::{discover_repos, DiscoverOptions};
+
+use git2::Repository;
+use tempfile::tempdir;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+fn init(root: &Path, path: &str) -> PathBuf {
+    let path = root.join(path);
+    fs::create_dir_all(&path).unwrap();
+    Repository::init(&path).unwrap();
+    path
+}
+
+fn found(root: &Path, options: &DiscoverOptions) -> Vec<PathBuf> {
+    discover_repos(root, options)
+        .into_iter()
+        .map(|found| found.unwrap())
+        .collect()
+}
+
+#[test]
+fn finds_nested_repos() {
+    let root = tempdir().unwrap();
+    let deep = init(root.path(), "github.com/org/repo");
+    let top = init(root.path(), "top");
+    let inner = init(root.path(), "top/vendor/inner");
+    init(root.path(), "web/node_modules/dep");
+    fs::create_dir_all(root.path().join("empty/dir")).unwrap();
+
+    let options = DiscoverOptions::default();
+    assert_eq!(found(root.path(), &options), vec![deep.clone(), top.clone()]);
+
+    let options = DiscoverOptions {
+        descend_into_repos: true,
+        ..DiscoverOptions::default()
+    };
+    assert_eq!(found(root.path(), &options), vec![deep, top, inner]);
+}
+
+#[test]
+fn respects_max_depth() {
+    let root = tempdir().unwrap();
+    let shallow = init(root.path(), "a");
+    init(root.path(), "b/c/d");
+
+    let options = DiscoverOptions {
+        max_depth: Some(2),
+        ..DiscoverOptions::default()
+    };
+    assert_eq!(found(root.path(), &options), vec![shallow]);
+}
+
+#[test]
+fn root_can_be_a_repo() {
+    let root = tempdir().unwrap();
+    Repository::init_bare(root.path()).unwrap();
+
+    assert_eq!(found(root.path(), &DiscoverOptions::default()), vec![root.path().to_path_buf()]);
+}
+
+#[cfg(unix)]
+#[test]
+fn follows_symlinks_once() {
+    let root = tempdir().unwrap();
+    let repo = init(root.path(), "real/repo");
+    std::os::unix::fs::symlink(root.path().join("real"), root.path().join("link")).unwrap();
+    std::os::unix::fs::symlink(root.path(), root.path().join("real/cycle")).unwrap();
+
+    assert_eq!(found(root.path(), &DiscoverOptions::default()), vec![repo.clone()]);
+
+    let options = DiscoverOptions {
+        follow_symlinks: true,
+        ..DiscoverOptions::default()
+    };
+    let found = found(root.path(), &options);
+    assert_eq!(found.len(), 1);
+    assert_eq!(fs::canonicalize(&found[0]).unwrap(), fs::canonicalize(repo).unwrap());
+}
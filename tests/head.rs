@@ -0,0 +1,46 @@
+mod common;
+
+use virtual_repo_hub::{get_status_path, Head};
+use virtual_repo_hub::backup::{check_backup, BackupPolicy, BackupProblem};
+
+use common::git;
+
+use tempfile::tempdir;
+
+#[test]
+fn reports_unborn_branch() {
+    let root = tempdir().unwrap();
+    git(root.path(), &["init", "-b", "main", "repo"]);
+
+    let status = get_status_path(root.path().join("repo")).unwrap();
+    assert_eq!(status.head, Head::Unborn { name: "main".to_string() });
+}
+
+#[test]
+fn checks_detached_head_against_remotes() {
+    let root = tempdir().unwrap();
+    git(root.path(), &["init", "-b", "master", "upstream"]);
+    let upstream = root.path().join("upstream");
+    git(&upstream, &["commit", "--allow-empty", "-m", "first"]);
+    git(&upstream, &["commit", "--allow-empty", "-m", "second"]);
+    git(root.path(), &["clone", "-q", "upstream", "clone"]);
+    let clone = root.path().join("clone");
+
+    let status = get_status_path(&clone).unwrap();
+    assert_eq!(status.head, Head::Branch { name: "master".to_string() });
+
+    // a pushed commit is safe to leave checked out
+    git(&clone, &["checkout", "-q", "--detach", "HEAD~1"]);
+    let first = git(&clone, &["rev-parse", "HEAD"]);
+    let status = get_status_path(&clone).unwrap();
+    assert_eq!(status.head, Head::Detached { commit: first, merged_in_remote: true });
+    assert!(check_backup(&status, &BackupPolicy::default()).backed_up);
+
+    // but a commit made on top of it is on no branch at all
+    git(&clone, &["commit", "--allow-empty", "-m", "detached"]);
+    let detached = git(&clone, &["rev-parse", "HEAD"]);
+    let status = get_status_path(&clone).unwrap();
+    assert_eq!(status.head, Head::Detached { commit: detached.clone(), merged_in_remote: false });
+    let verdict = check_backup(&status, &BackupPolicy::default());
+    assert_eq!(verdict.problems, vec![BackupProblem::DetachedHead { commit: detached }]);
+}
@@ -0,0 +1,162 @@
+mod common;
+
+use virtual_repo_hub::discover::DiscoverOptions;
+use virtual_repo_hub::{BranchStatus, StatusOptions};
+use virtual_repo_hub::index::{read_repo, scan, Index, IndexedRemote, IndexedRepo};
+
+use common::git;
+
+use git2::{Repository, Signature, Time};
+use tempfile::tempdir;
+
+use std::fs;
+use std::path::Path;
+
+fn commit(repo: &Repository, seconds: i64) {
+    let signature = Signature::new("vrh", "vrh@example.com", &Time::new(seconds, 0)).unwrap();
+    let tree = repo.find_tree(repo.index().unwrap().write_tree().unwrap()).unwrap();
+    repo.commit(Some("HEAD"), &signature, &signature, "commit", &tree, &[]).unwrap();
+}
+
+#[test]
+fn scans_and_saves_starred_repos() {
+    let root = tempdir().unwrap();
+    let work = root.path().join("work");
+    let upstream = Repository::init(work.join("upstream")).unwrap();
+    commit(&upstream, 1_500_000_000);
+    let clone_path = work.join("nested/clone");
+    let url = format!("file://{}", work.join("upstream").display());
+    Repository::clone(&url, &clone_path).unwrap();
+    let broken = root.path().join("play/broken");
+    fs::create_dir_all(broken.join(".git")).unwrap();
+
+    let starred = [("work", work.as_path()), ("play", broken.parent().unwrap())];
+    let (index, errors) = scan(&starred, &DiscoverOptions::default(), 2, None);
+    assert!(errors.is_empty(), "{:?}", errors);
+
+    let paths: Vec<_> = index.repos.iter().map(|repo| Path::new(&repo.path)).collect();
+    assert_eq!(paths, vec![broken.as_path(), clone_path.as_path(), &work.join("upstream")]);
+
+    let clone = index.get(&clone_path).unwrap();
+    assert_eq!(clone.alias.as_deref(), Some("work"));
+    assert_eq!(clone.remotes, vec![IndexedRemote { name: "origin".into(), url: Some(url) }]);
+    assert_eq!(clone.default_branch.as_deref(), Some("master"));
+    assert_eq!(clone.last_commit, Some(1_500_000_000));
+    assert!(clone.status.is_some());
+    assert!(clone.error.is_none());
+
+    let broken = index.get(&broken).unwrap();
+    assert_eq!(broken.alias.as_deref(), Some("play"));
+    assert!(broken.status.is_none());
+    assert!(broken.error.is_some());
+
+    let saved = root.path().join("config/device/default.index.json");
+    assert_eq!(Index::load(&saved).unwrap(), Index::default());
+    index.save(&saved).unwrap();
+    assert_eq!(Index::load(&saved).unwrap(), index);
+
+    // an index from another version is rebuilt rather than misread
+    fs::write(&saved, r#"{"schema_version": 0, "updated": 0, "repos": [{"stashes": 0}]}"#).unwrap();
+    assert_eq!(Index::load(&saved).unwrap(), Index::default());
+}
+
+#[test]
+fn reuses_unchanged_repos() {
+    let root = tempdir().unwrap();
+    let path = root.path().join("repo");
+    let repo = Repository::init(&path).unwrap();
+    commit(&repo, 1_500_000_000);
+
+    let starred = [("root", root.path())];
+    let (mut index, _) = scan(&starred, &DiscoverOptions::default(), 1, None);
+    // mark the record, so it can be told apart from one that was read again
+    index.repos[0].status.as_mut().unwrap().bare = true;
+    let marked = index.repos[0].clone();
+
+    let (rescanned, _) = scan(&starred, &DiscoverOptions::default(), 1, Some(&index));
+    assert_eq!(rescanned.repos, vec![marked.clone()]);
+    let read = read_repo(&path, None, &StatusOptions::default(), Some(&marked)).unwrap();
+    assert_eq!(read.status, marked.status);
+
+    // changed paths are never cached, so asking for them always reads the repo
+    let options = StatusOptions {
+        include_paths: true,
+        ..StatusOptions::default()
+    };
+    let read = read_repo(&path, None, &options, Some(&marked)).unwrap();
+    assert!(!read.status.unwrap().bare);
+
+    // an untracked file and a new branch are both noticed
+    fs::write(path.join("new.txt"), "new").unwrap();
+    let read = read_repo(&path, None, &StatusOptions::default(), Some(&marked)).unwrap();
+    assert_eq!(read.status.unwrap().files.untracked, 1);
+
+    let (mut index, _) = scan(&starred, &DiscoverOptions::default(), 1, None);
+    index.repos[0].status.as_mut().unwrap().bare = true;
+    let head = repo.head().unwrap().peel_to_commit().unwrap();
+    repo.branch("feature", &head, false).unwrap();
+    let (rescanned, _) = scan(&starred, &DiscoverOptions::default(), 1, Some(&index));
+    let status = rescanned.repos[0].status.as_ref().unwrap();
+    assert!(!status.bare);
+    assert!(status.branches.contains_key("feature"));
+
+    // a fetch that changes no refs still rewrites FETCH_HEAD, which has the last fetch time
+    let fetch_head = path.join(".git/FETCH_HEAD");
+    fs::write(&fetch_head, "").unwrap();
+    let (mut index, _) = scan(&starred, &DiscoverOptions::default(), 1, None);
+    index.repos[0].status.as_mut().unwrap().bare = true;
+    fs::write(&fetch_head, "fetched\n").unwrap();
+    let (rescanned, _) = scan(&starred, &DiscoverOptions::default(), 1, Some(&index));
+    assert!(!rescanned.repos[0].status.as_ref().unwrap().bare);
+}
+
+#[test]
+fn notices_pushes_from_other_worktrees() {
+    let root = tempdir().unwrap();
+    git(root.path(), &["init", "-q", "--bare", "-b", "master", "upstream"]);
+    git(root.path(), &["clone", "-q", "upstream", "clone"]);
+    let clone = root.path().join("clone");
+    git(&clone, &["commit", "-q", "--allow-empty", "-m", "first"]);
+    git(&clone, &["push", "-q", "-u", "origin", "master"]);
+    git(&clone, &["worktree", "add", "-q", "-b", "feature", "../feature"]);
+    let feature = root.path().join("feature");
+    git(&feature, &["push", "-q", "-u", "origin", "feature"]);
+    git(&feature, &["commit", "-q", "--allow-empty", "-m", "feature"]);
+
+    let ahead = |repo: &IndexedRepo| {
+        match &repo.status.as_ref().unwrap().branches["feature"] {
+            BranchStatus::TrackingBranch(tracking) => tracking.ahead,
+            status => panic!("{:?}", status),
+        }
+    };
+    let read = read_repo(&feature, None, &StatusOptions::default(), None).unwrap();
+    assert_eq!(ahead(&read), 1);
+
+    // the refs are shared, so the push changes the status of the other worktree too
+    git(&clone, &["push", "-q", "origin", "feature"]);
+    let read = read_repo(&feature, None, &StatusOptions::default(), Some(&read)).unwrap();
+    assert_eq!(ahead(&read), 0);
+}
+
+#[test]
+fn notices_hooks_installed_in_hooks_path() {
+    let root = tempdir().unwrap();
+    git(root.path(), &["init", "-q", "repo"]);
+    let path = root.path().join("repo");
+    fs::write(path.join(".gitattributes"), "*.bin filter=lfs diff=lfs merge=lfs -text\n").unwrap();
+    git(&path, &["add", ".gitattributes"]);
+    git(&path, &["commit", "-q", "-m", "lfs"]);
+    let hooks = root.path().join("hooks");
+    fs::create_dir(&hooks).unwrap();
+    git(&path, &["config", "core.hooksPath", &hooks.to_string_lossy()]);
+
+    let pre_push_hook = |repo: &IndexedRepo| {
+        repo.status.as_ref().unwrap().lfs.as_ref().unwrap().pre_push_hook
+    };
+    let read = read_repo(&path, None, &StatusOptions::default(), None).unwrap();
+    assert!(!pre_push_hook(&read));
+
+    fs::write(hooks.join("pre-push"), "#!/bin/sh\ngit lfs pre-push \"$@\"\n").unwrap();
+    let read = read_repo(&path, None, &StatusOptions::default(), Some(&read)).unwrap();
+    assert!(pre_push_hook(&read));
+}
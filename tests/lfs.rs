@@ -0,0 +1,75 @@
+mod common;
+
+use virtual_repo_hub::{get_status_path, LfsObject};
+use virtual_repo_hub::backup::{check_backup, BackupPolicy, BackupProblem};
+
+use common::git;
+
+use tempfile::tempdir;
+
+use std::fs;
+use std::path::Path;
+
+/// Commit `path` the way `git lfs` would: a pointer file in the repo and the contents in
+/// `.git/lfs/objects`. git-lfs itself isn't needed, since only the files it leaves are read.
+fn commit_lfs_file(repo: &Path, path: &str, oid: &str, size: u64) {
+    let pointer = format!(
+        "version https://git-lfs.github.com/spec/v1\noid sha256:{}\nsize {}\n",
+        oid,
+        size,
+    );
+    fs::write(repo.join(path), pointer).unwrap();
+    let object_dir = repo.join(".git/lfs/objects").join(&oid[..2]).join(&oid[2..4]);
+    fs::create_dir_all(&object_dir).unwrap();
+    fs::write(object_dir.join(oid), vec![0; size as usize]).unwrap();
+    git(repo, &["add", path]);
+    git(repo, &["commit", "-q", "-m", path]);
+}
+
+#[test]
+fn reports_lfs_objects_not_on_a_remote() {
+    let root = tempdir().unwrap();
+    git(root.path(), &["init", "-q", "--bare", "-b", "master", "upstream"]);
+    git(root.path(), &["clone", "-q", "upstream", "clone"]);
+    let clone = root.path().join("clone");
+    assert_eq!(get_status_path(&clone).unwrap().lfs, None);
+
+    fs::write(clone.join(".gitattributes"), "*.bin filter=lfs diff=lfs merge=lfs -text\n").unwrap();
+    fs::write(clone.join(".git/hooks/pre-push"), "#!/bin/sh\ngit lfs pre-push \"$@\"\n").unwrap();
+    git(&clone, &["add", ".gitattributes"]);
+    let pushed = "a".repeat(64);
+    commit_lfs_file(&clone, "pushed.bin", &pushed, 10);
+    commit_lfs_file(&clone, "deleted.bin", &"c".repeat(64), 5);
+    git(&clone, &["rm", "-q", "deleted.bin"]);
+    git(&clone, &["commit", "-q", "-m", "deleted.bin"]);
+    // the hook doesn't run, it's only read
+    git(&clone, &["push", "-q", "--no-verify", "origin", "master"]);
+    let local = "b".repeat(64);
+    commit_lfs_file(&clone, "local.bin", &local, 20);
+
+    let status = get_status_path(&clone).unwrap();
+    let lfs = status.lfs.as_ref().unwrap();
+    assert!(lfs.pre_push_hook);
+    assert_eq!(lfs.unpushed_objects, vec![LfsObject {
+        oid: local,
+        size: 20,
+        path: "local.bin".to_string(),
+    }]);
+    let verdict = check_backup(&status, &BackupPolicy::default());
+    let problem = BackupProblem::UnpushedLfsObjects { objects: 1, size: 20 };
+    assert_eq!(problem.to_string(), "1 LFS objects (20 bytes) may only be stored locally");
+    assert!(verdict.problems.contains(&problem));
+    assert!(!verdict.problems.contains(&BackupProblem::MissingLfsHook));
+
+    // without the hook, a pushed branch may have left its objects behind, but only the checked
+    // out files are searched for them
+    fs::remove_file(clone.join(".git/hooks/pre-push")).unwrap();
+    let status = get_status_path(&clone).unwrap();
+    let lfs = status.lfs.as_ref().unwrap();
+    assert!(!lfs.pre_push_hook);
+    let paths: Vec<_> = lfs.unpushed_objects.iter().map(|object| object.path.as_str()).collect();
+    assert_eq!(paths, vec!["local.bin", "pushed.bin"]);
+    let verdict = check_backup(&status, &BackupPolicy::default());
+    assert!(verdict.problems.contains(&BackupProblem::MissingLfsHook));
+    assert!(verdict.problems.contains(&BackupProblem::UnpushedLfsObjects { objects: 2, size: 30 }));
+}
@@ -0,0 +1,96 @@
+mod common;
+
+use virtual_repo_hub::{get_status_path, OperationKind, Progress};
+use virtual_repo_hub::backup::{check_backup, BackupPolicy, BackupProblem};
+
+use common::{git, try_git};
+
+use tempfile::tempdir;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+fn commit(dir: &Path, file: &str, contents: &str) {
+    fs::write(dir.join(file), contents).unwrap();
+    git(dir, &["add", file]);
+    git(dir, &["commit", "-m", contents]);
+}
+
+/// A repo whose `topic` branch changes `a.txt` in two commits, conflicting with `master`.
+fn diverged_repo(root: &Path) -> PathBuf {
+    let path = root.join("repo");
+    git(root, &["init", "-b", "master", "repo"]);
+    commit(&path, "a.txt", "base");
+    git(&path, &["checkout", "-b", "topic"]);
+    commit(&path, "a.txt", "topic 1");
+    commit(&path, "a.txt", "topic 2");
+    git(&path, &["checkout", "master"]);
+    commit(&path, "a.txt", "master");
+    path
+}
+
+#[test]
+fn reports_merge_conflicts() {
+    let root = tempdir().unwrap();
+    let path = diverged_repo(root.path());
+    assert!(!try_git(&path, &["merge", "topic"]));
+
+    let status = get_status_path(&path).unwrap();
+    let operation = status.operation.as_ref().unwrap();
+    assert_eq!(operation.kind, OperationKind::Merge);
+    assert_eq!(operation.branch.as_deref(), Some("master"));
+    assert_eq!(operation.onto, None);
+    assert_eq!(operation.progress, None);
+    assert_eq!(operation.conflicted, vec!["a.txt"]);
+
+    let verdict = check_backup(&status, &BackupPolicy::default());
+    let problem = BackupProblem::OperationInProgress { operation: operation.clone() };
+    assert_eq!(problem.to_string(), "merge in progress on master, 1 conflicted files");
+    assert!(verdict.problems.contains(&problem));
+
+    git(&path, &["merge", "--abort"]);
+    assert_eq!(get_status_path(&path).unwrap().operation, None);
+}
+
+#[test]
+fn reports_rebase_progress() {
+    let root = tempdir().unwrap();
+    let path = diverged_repo(root.path());
+    let master = git2::Repository::open(&path).unwrap()
+        .revparse_single("master").unwrap()
+        .id()
+        .to_string();
+    git(&path, &["checkout", "topic"]);
+
+    // the apply backend keeps its state in rebase-apply
+    assert!(!try_git(&path, &["rebase", "--apply", "master"]));
+    let operation = get_status_path(&path).unwrap().operation.unwrap();
+    assert_eq!(operation.kind, OperationKind::Rebase);
+    assert_eq!(operation.branch.as_deref(), Some("topic"));
+    assert_eq!(operation.onto.as_ref(), Some(&master));
+    assert_eq!(operation.progress, Some(Progress { step: 1, total: 2 }));
+    assert_eq!(operation.conflicted, vec!["a.txt"]);
+    git(&path, &["rebase", "--abort"]);
+
+    // and the merge backend in rebase-merge
+    let interactive = ["-c", "sequence.editor=true", "rebase", "--interactive", "master"];
+    assert!(!try_git(&path, &interactive));
+    let operation = get_status_path(&path).unwrap().operation.unwrap();
+    assert_eq!(operation.kind, OperationKind::InteractiveRebase);
+    assert_eq!(operation.branch.as_deref(), Some("topic"));
+    assert_eq!(operation.onto.as_ref(), Some(&master));
+    assert_eq!(operation.progress, Some(Progress { step: 1, total: 2 }));
+    assert_eq!(operation.conflicted, vec!["a.txt"]);
+}
+
+#[test]
+fn reports_bisect() {
+    let root = tempdir().unwrap();
+    let path = diverged_repo(root.path());
+    git(&path, &["bisect", "start", "topic", "master~1"]);
+
+    let operation = get_status_path(&path).unwrap().operation.unwrap();
+    assert_eq!(operation.kind, OperationKind::Bisect);
+    assert_eq!(operation.branch.as_deref(), Some("master"));
+    assert!(operation.conflicted.is_empty());
+}
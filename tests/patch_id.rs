@@ -0,0 +1,94 @@
+mod common;
+
+use virtual_repo_hub::{get_status_path, BranchStatus, Equivalence};
+
+use common::git;
+
+use tempfile::tempdir;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A clone of a bare upstream with one pushed commit, on master.
+fn clone(root: &Path) -> PathBuf {
+    git(root, &["init", "-q", "--bare", "-b", "master", "upstream"]);
+    git(root, &["clone", "-q", "upstream", "clone"]);
+    let clone = root.join("clone");
+    fs::write(clone.join("README"), "readme\n").unwrap();
+    git(&clone, &["add", "README"]);
+    git(&clone, &["commit", "-q", "-m", "first"]);
+    git(&clone, &["push", "-q", "origin", "master"]);
+    clone
+}
+
+/// Commit `contents` as `file` on the current branch.
+fn commit(repo: &Path, file: &str, contents: &[u8]) {
+    fs::write(repo.join(file), contents).unwrap();
+    git(repo, &["add", file]);
+    git(repo, &["commit", "-q", "-m", file]);
+}
+
+fn feature_status(clone: &Path) -> BranchStatus {
+    get_status_path(clone).unwrap().branches["feature"].clone()
+}
+
+#[test]
+fn finds_rebased_branches() {
+    let root = tempdir().unwrap();
+    let clone = clone(root.path());
+    git(&clone, &["checkout", "-q", "-b", "feature"]);
+    commit(&clone, "a.txt", b"a\n");
+    commit(&clone, "logo.png", b"\x89PNG\0one");
+
+    // the same changes land on master on top of other work, as if the branch was rebased
+    git(&clone, &["checkout", "-q", "master"]);
+    commit(&clone, "b.txt", b"b\n");
+    git(&clone, &["cherry-pick", "feature~1", "feature"]);
+    git(&clone, &["push", "-q", "origin", "master"]);
+
+    assert_eq!(feature_status(&clone), BranchStatus::EquivalentInRemote { via: Equivalence::Rebased });
+}
+
+#[test]
+fn binary_files_with_the_same_path_differ() {
+    let root = tempdir().unwrap();
+    let clone = clone(root.path());
+    git(&clone, &["checkout", "-q", "-b", "feature"]);
+    commit(&clone, "logo.png", b"\x89PNG\0feature");
+
+    git(&clone, &["checkout", "-q", "master"]);
+    commit(&clone, "logo.png", b"\x89PNG\0master");
+    git(&clone, &["push", "-q", "origin", "master"]);
+
+    assert_eq!(feature_status(&clone), BranchStatus::LocalBranch { merged_in_remote: false });
+}
+
+#[test]
+fn file_modes_differ() {
+    let root = tempdir().unwrap();
+    let clone = clone(root.path());
+    git(&clone, &["checkout", "-q", "-b", "feature"]);
+    fs::write(clone.join("run.sh"), "#!/bin/sh\n").unwrap();
+    git(&clone, &["add", "--chmod=+x", "run.sh"]);
+    git(&clone, &["commit", "-q", "-m", "run.sh"]);
+    // only the index was made executable, which would stop the checkout below
+    git(&clone, &["reset", "-q", "--hard"]);
+
+    // the same file, but not executable
+    git(&clone, &["checkout", "-q", "master"]);
+    commit(&clone, "run.sh", b"#!/bin/sh\n");
+    git(&clone, &["push", "-q", "origin", "master"]);
+
+    assert_eq!(feature_status(&clone), BranchStatus::LocalBranch { merged_in_remote: false });
+}
+
+#[test]
+fn unrelated_remote_history_is_not_searched() {
+    let root = tempdir().unwrap();
+    let clone = clone(root.path());
+    // the same change as the pushed commit, but on a branch that shares no history with it
+    git(&clone, &["checkout", "-q", "--orphan", "feature"]);
+    git(&clone, &["commit", "-q", "-m", "readme"]);
+
+    assert_eq!(feature_status(&clone), BranchStatus::LocalBranch { merged_in_remote: false });
+}
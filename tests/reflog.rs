@@ -0,0 +1,48 @@
+mod common;
+
+use virtual_repo_hub::{get_status_path, get_status_with_options, open_repo, StatusOptions};
+use virtual_repo_hub::backup::{check_backup, BackupPolicy, BackupProblem};
+
+use common::git;
+
+use tempfile::tempdir;
+
+#[test]
+fn deep_scan_finds_reflog_only_commits() {
+    let root = tempdir().unwrap();
+    git(root.path(), &["init", "-b", "master", "repo"]);
+    let path = root.path().join("repo");
+    git(&path, &["commit", "--allow-empty", "-m", "first"]);
+    git(&path, &["commit", "--allow-empty", "-m", "typo"]);
+    let amended = git(&path, &["rev-parse", "HEAD"]);
+    git(&path, &["commit", "--amend", "--allow-empty", "-m", "fixed"]);
+
+    git(&path, &["checkout", "-q", "-b", "doomed"]);
+    git(&path, &["commit", "--allow-empty", "-m", "deleted work"]);
+    let deleted = git(&path, &["rev-parse", "HEAD"]);
+    git(&path, &["checkout", "-q", "master"]);
+    git(&path, &["branch", "-q", "-D", "doomed"]);
+
+    assert_eq!(get_status_path(&path).unwrap().reflog_commits, None);
+
+    let options = StatusOptions {
+        deep: true,
+        ..StatusOptions::default()
+    };
+    let status = get_status_with_options(&mut open_repo(&path).unwrap(), &options).unwrap();
+    let mut found: Vec<_> = status.reflog_commits.as_ref().unwrap().iter()
+        .map(|commit| (commit.id.clone(), commit.summary.as_str()))
+        .collect();
+    found.sort();
+    let mut expected = vec![(amended, "typo"), (deleted, "deleted work")];
+    expected.sort();
+    assert_eq!(found, expected);
+
+    // they are at risk, but only a warning
+    let verdict = check_backup(&status, &BackupPolicy::default());
+    let is_reflog_commit = |problem: &&BackupProblem| {
+        matches!(problem, BackupProblem::ReflogCommit { .. })
+    };
+    assert_eq!(verdict.warnings.iter().filter(is_reflog_commit).count(), 2);
+    assert_eq!(verdict.problems.iter().filter(is_reflog_commit).count(), 0);
+}
@@ -0,0 +1,31 @@
+use virtual_repo_hub::RemoteLocation;
+
+fn forge(host: &str) -> RemoteLocation {
+    RemoteLocation::Forge { host: host.to_string() }
+}
+
+fn network(host: &str) -> RemoteLocation {
+    RemoteLocation::Network { host: host.to_string() }
+}
+
+#[test]
+fn classifies_url_syntaxes() {
+    let cases = [
+        ("https://github.com/org/repo.git", forge("github.com")),
+        ("ssh://git@GitLab.com:22/org/repo.git", forge("gitlab.com")),
+        ("git@github.com:org/repo.git", forge("github.com")),
+        ("git://git.example.org/repo", network("git.example.org")),
+        ("nas:backups/repo.git", network("nas")),
+        ("ssh://user@[::1]:2222/repo", network("::1")),
+        ("/srv/git/repo.git", RemoteLocation::Local),
+        ("../repo", RemoteLocation::Local),
+        ("file:///srv/git/repo.git", RemoteLocation::Local),
+        ("./dir:with/colon", RemoteLocation::Local),
+        ("C:/Users/me/repo", RemoteLocation::Local),
+        ("ext::ssh -p 2222 host %S repo", RemoteLocation::Unknown),
+    ];
+
+    for (url, expected) in &cases {
+        assert_eq!(&RemoteLocation::of_url(url), expected, "{}", url);
+    }
+}
@@ -1,3 +1,5 @@
+#![allow(clippy::result_large_err)]
+
 use virtual_repo_hub::{RepoStatus, get_status_path};
 
 use serde::{Serialize, Deserialize};
@@ -13,6 +15,7 @@ use std::io;
 use std::io::{Write, BufWriter};
 
 const DEFAULT_FILE: &str = "default.txt";
+const UNTRACKED_FILE: &str = "untracked.txt";
 
 pub struct GenState {
     use_directory: Option<(usize, PathBuf)>,
@@ -59,19 +62,16 @@ impl GenState {
 
     pub fn cleanup(self) {
         for (_, v) in self.repos {
-            match v.close() {
-                Err(err) => {
-                    eprintln!("There was an issue cleaning up the generator state:");
-                    eprintln!("\tTempDir reported an error deleting itself.");
-                    eprintln!("{}", err);
-                },
-                Ok(_) => {},
+            if let Err(err) = v.close() {
+                eprintln!("There was an issue cleaning up the generator state:");
+                eprintln!("\tTempDir reported an error deleting itself.");
+                eprintln!("{}", err);
             }
         }
     }
 
     pub fn init(&mut self, bare: bool) {
-        if let Some(_) = self.active {
+        if self.active.is_some() {
             unimplemented!();
         }
 
@@ -90,7 +90,7 @@ impl GenState {
         self.config();
     }
 
-    pub fn clone(&mut self) {
+    pub fn clone(&mut self, bare: bool, mirror: bool) {
         self.assert_active();
 
         let clone = self.alloc_dir().unwrap();
@@ -106,7 +106,15 @@ impl GenState {
             .to_str()
             .unwrap();
 
-        run_git(&["clone", source_path, clone_path]);
+        let mut args = vec!["clone"];
+        if bare {
+            args.push("--bare");
+        }
+        if mirror {
+            args.push("--mirror");
+        }
+        args.extend(&[source_path, clone_path]);
+        run_git(&args);
 
         set_current_dir(clone.path())
             .expect("failed to set working directory for cloned repo");
@@ -157,6 +165,50 @@ impl GenState {
         run_git(&["add", DEFAULT_FILE]);
     }
 
+    pub fn untracked(&mut self) {
+        let mut f = GenState::get_file(UNTRACKED_FILE);
+        f.write_all(b"untracked!\n")
+            .unwrap();
+        f.flush()
+            .unwrap();
+    }
+
+    pub fn branch(&mut self, name: &str, orphan: bool) {
+        self.assert_active();
+
+        let flag = if orphan { "--orphan" } else { "-b" };
+        run_git(&["checkout", flag, name]);
+    }
+
+    pub fn checkout(&mut self, name: &str) {
+        self.assert_active();
+
+        run_git(&["checkout", name]);
+    }
+
+    pub fn merge(&mut self, branch: &str, squash: bool) {
+        self.assert_active();
+
+        if squash {
+            run_git(&["merge", "--squash", branch]);
+            run_git(&["commit", "-m", "squashed commit"]);
+        } else {
+            run_git(&["merge", "--no-edit", branch]);
+        }
+    }
+
+    pub fn push(&mut self) {
+        self.assert_active();
+
+        run_git(&["push"]);
+    }
+
+    pub fn track(&mut self, upstream: &str) {
+        self.assert_active();
+
+        run_git(&["branch", "--set-upstream-to", upstream]);
+    }
+
     fn get_file<P: AsRef<Path>>(path: P) -> BufWriter<File> {
         let f = fs::OpenOptions::new()
             .create(true)
@@ -179,7 +231,22 @@ impl GenState {
             },
             None =>
                 tempdir()
-                    .map(|dir| RepoLocation::Temp(dir))
+                    .map(RepoLocation::Temp)
+        }
+    }
+
+    /// Replace remote URLs that point at a generated repo with `$<name>`, and fetch times with 0,
+    /// since the real values are only known while the test runs.
+    pub fn mask_runtime_values(&self, status: &mut RepoStatus) {
+        for remote in &mut status.remotes {
+            remote.last_fetch = remote.last_fetch.map(|_| 0);
+            for url in remote.url.iter_mut().chain(remote.push_url.iter_mut()) {
+                let repo = self.repos.iter()
+                    .find(|(_, location)| Path::new(url.as_str()) == location.path());
+                if let Some((name, _)) = repo {
+                    *url = format!("${}", name);
+                }
+            }
         }
     }
 
@@ -208,14 +275,37 @@ pub enum GenCommand {
         #[serde(default="r#false")]
         bare: bool,
     },
-    Clone {},
+    Clone {
+        #[serde(default="r#false")]
+        bare: bool,
+        #[serde(default="r#false")]
+        mirror: bool,
+    },
     Commit {
         repeat: u32,
     },
     Modify {},
     Stage {},
+    Untracked {},
+    Branch {
+        name: String,
+        #[serde(default="r#false")]
+        orphan: bool,
+    },
+    Track {
+        upstream: String,
+    },
+    Checkout {
+        name: String,
+    },
+    Merge {
+        branch: String,
+        #[serde(default="r#false")]
+        squash: bool,
+    },
+    Push {},
     Expect {
-        status: RepoStatus,
+        status: Box<RepoStatus>,
     },
 }
 
@@ -224,16 +314,23 @@ impl GenCommand {
         use GenCommand::*;
         match self {
             Init { bare } => state.init(*bare),
-            Clone {} => state.clone(),
+            Clone { bare, mirror } => state.clone(*bare, *mirror),
             Commit { repeat } => state.commit(*repeat),
             Modify {} => state.modify(),
             Stage {} => state.stage(),
+            Untracked {} => state.untracked(),
+            Branch { name, orphan } => state.branch(name, *orphan),
+            Track { upstream } => state.track(upstream),
+            Checkout { name } => state.checkout(name),
+            Merge { branch, squash } => state.merge(branch, *squash),
+            Push {} => state.push(),
             Expect { status } => {
-                let actual = get_status_path(current_dir().unwrap())
+                let mut actual = get_status_path(current_dir().unwrap())
                     .expect("failed to get actual repo status");
-                if status != &actual {
+                state.mask_runtime_values(&mut actual);
+                if **status != actual {
                     return Err(AssertionError {
-                        expected: status.clone(),
+                        expected: (**status).clone(),
                         actual,
                     });
                 }
@@ -271,9 +368,9 @@ fn execute_yaml_inner<P1: AsRef<Path> + std::fmt::Debug, P2: Into<PathBuf>>(
         .unwrap();
 
     let contents = fs::read_to_string(&path)
-        .expect(&format!("failed to open yaml at {:?}", &path));
+        .unwrap_or_else(|_| panic!("failed to open yaml at {:?}", &path));
     let commands: Vec<GenCommand> = serde_yaml::from_str(&contents)
-        .expect(&format!("failed to read commands at {:?}", &path));
+        .unwrap_or_else(|_| panic!("failed to read commands at {:?}", &path));
 
     let target_directory = target_directory.map(|dir| dir.into());
     let mut state = GenState::new(target_directory);
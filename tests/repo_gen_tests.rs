@@ -1,4 +1,4 @@
-use virtual_repo_hub::RepoStatus;
+use virtual_repo_hub::{RepoStatus, FileStatus, Head};
 
 use std::collections::HashMap;
 use std::env::set_current_dir;
@@ -17,9 +17,11 @@ const EXAMPLE1: &str = "
     - expect:
         status:
             bare: false
-            clean_status: true
-            clean_state: true
-            stashes: 0
+            head:
+                kind: unborn
+                name: master
+            files: {}
+            stashes: []
             remotes: []
             branches: {}";
 
@@ -63,12 +65,18 @@ fn parses_commands() {
         _ => panic!(),
     };
 
-    assert_eq!(status, &RepoStatus {
+    assert_eq!(**status, RepoStatus {
         bare: false,
-        clean_status: true,
-        clean_state: true,
-        stashes: 0,
+        head: Head::Unborn { name: "master".to_string() },
+        files: FileStatus::default(),
+        operation: None,
+        stashes: Vec::new(),
+        unpushed_tags: Vec::new(),
+        reflog_commits: None,
         remotes: Vec::new(),
         branches: HashMap::new(),
+        submodules: Vec::new(),
+        worktrees: Vec::new(),
+        lfs: None,
     });
 }
@@ -0,0 +1,89 @@
+mod common;
+
+use virtual_repo_hub::{get_status_path, open_repo};
+use virtual_repo_hub::stash::{list_stashes, stashes_to_branches, stashes_to_bundle};
+
+use common::git;
+
+use tempfile::tempdir;
+
+use std::fs;
+use std::path::Path;
+
+fn repo_with_stashes(root: &Path) -> std::path::PathBuf {
+    let path = root.join("repo");
+    git(root, &["init", "repo"]);
+    fs::write(path.join("a.txt"), "a").unwrap();
+    fs::write(path.join("b.txt"), "b").unwrap();
+    git(&path, &["add", "."]);
+    git(&path, &["commit", "-m", "first"]);
+    git(&path, &["checkout", "-b", "topic"]);
+
+    fs::write(path.join("a.txt"), "changed").unwrap();
+    git(&path, &["stash", "push", "-m", "older"]);
+
+    fs::write(path.join("b.txt"), "changed").unwrap();
+    fs::write(path.join("new.txt"), "untracked").unwrap();
+    git(&path, &["stash", "push", "--include-untracked"]);
+
+    path
+}
+
+#[test]
+fn lists_stash_details() {
+    let root = tempdir().unwrap();
+    let path = repo_with_stashes(root.path());
+
+    let stashes = list_stashes(&mut open_repo(&path).unwrap()).unwrap();
+    assert_eq!(stashes.len(), 2);
+
+    assert_eq!(stashes[0].index, 0);
+    assert!(stashes[0].message.starts_with("WIP on topic: "), "{}", stashes[0].message);
+    assert_eq!(stashes[0].branch.as_deref(), Some("topic"));
+    assert_eq!(stashes[0].files, 2);
+
+    assert_eq!(stashes[1].index, 1);
+    assert_eq!(stashes[1].message, "On topic: older");
+    assert_eq!(stashes[1].branch.as_deref(), Some("topic"));
+    assert_eq!(stashes[1].files, 1);
+    assert!(stashes[1].time > 0);
+
+    assert_eq!(get_status_path(&path).unwrap().stashes, stashes);
+}
+
+#[test]
+fn exports_stashes_as_branches() {
+    let root = tempdir().unwrap();
+    let path = repo_with_stashes(root.path());
+    let mut repo = open_repo(&path).unwrap();
+
+    let names = stashes_to_branches(&mut repo, "stash/").unwrap();
+    assert_eq!(names.len(), 2);
+    // exporting again doesn't create anything new
+    assert!(stashes_to_branches(&mut repo, "stash/").unwrap().is_empty());
+
+    // the branch holds the stash itself, untracked files included
+    git(&path, &["stash", "clear"]);
+    git(&path, &["stash", "apply", &names[0]]);
+    assert_eq!(fs::read_to_string(path.join("new.txt")).unwrap(), "untracked");
+}
+
+#[test]
+fn exports_stashes_as_bundle() {
+    let root = tempdir().unwrap();
+    let path = repo_with_stashes(root.path());
+    let bundle = root.path().join("stashes.bundle");
+
+    let names = stashes_to_bundle(&mut open_repo(&path).unwrap(), "stash/", &bundle).unwrap();
+    assert_eq!(names.len(), 2);
+
+    // the bundle restores into a repo that shares no history with the original
+    git(root.path(), &["init", "restored"]);
+    let restored = root.path().join("restored");
+    git(&restored, &["bundle", "verify", bundle.to_str().unwrap()]);
+    git(&restored, &["fetch", bundle.to_str().unwrap(), "refs/heads/*:refs/heads/*"]);
+    let branches = git(&restored, &["branch", "--list", "stash/*"]);
+    for name in &names {
+        assert!(branches.contains(name.as_str()), "{} not in {}", name, branches);
+    }
+}
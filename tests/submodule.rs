@@ -0,0 +1,70 @@
+mod common;
+
+use virtual_repo_hub::{get_status_path, Head};
+use virtual_repo_hub::backup::{check_backup, BackupPolicy, BackupProblem};
+
+use common::git;
+
+use tempfile::tempdir;
+
+use std::fs;
+
+#[test]
+fn reports_submodule_status() {
+    let root = tempdir().unwrap();
+    git(root.path(), &["init", "-q", "-b", "master", "lib"]);
+    git(&root.path().join("lib"), &["commit", "--allow-empty", "-m", "lib"]);
+    git(root.path(), &["init", "-q", "-b", "master", "app"]);
+    let app = root.path().join("app");
+    git(&app, &["submodule", "add", "-q", "../lib", "vendor/lib"]);
+    git(&app, &["commit", "-q", "-m", "add lib"]);
+
+    let status = get_status_path(&app).unwrap();
+    assert_eq!(status.submodules.len(), 1);
+    let submodule = &status.submodules[0];
+    assert_eq!(submodule.path, "vendor/lib");
+    assert_eq!(submodule.url.as_deref(), Some("../lib"));
+    assert_eq!(submodule.recorded_in_remote, Some(true));
+    assert!(submodule.error.is_none());
+    let sub_status = submodule.status.as_ref().unwrap();
+    assert_eq!(sub_status.head, Head::Branch { name: "master".to_string() });
+    assert!(check_backup(&status, &BackupPolicy::default()).problems.iter().all(|problem| {
+        !matches!(problem, BackupProblem::UnpushedSubmoduleCommit { .. })
+    }));
+
+    // commit in the submodule and record that in the superproject without pushing the submodule
+    let lib = app.join("vendor/lib");
+    git(&lib, &["checkout", "-q", "--detach"]);
+    git(&lib, &["commit", "--allow-empty", "-m", "local fix"]);
+    let fix = git(&lib, &["rev-parse", "HEAD"]);
+    fs::write(lib.join("wip.txt"), "wip").unwrap();
+    git(&app, &["commit", "-q", "-am", "use local fix"]);
+
+    let status = get_status_path(&app).unwrap();
+    let submodule = &status.submodules[0];
+    assert_eq!(submodule.recorded.as_ref(), Some(&fix));
+    assert_eq!(submodule.recorded_in_remote, Some(false));
+    assert_eq!(submodule.status.as_ref().unwrap().files.untracked, 1);
+    // the untracked file is only reported in the submodule, not as a change to the superproject
+    assert!(status.files.is_clean());
+
+    let verdict = check_backup(&status, &BackupPolicy::default());
+    assert!(verdict.problems.contains(&BackupProblem::UnpushedSubmoduleCommit {
+        path: "vendor/lib".to_string(),
+        commit: fix,
+    }));
+    // the submodule's detached HEAD is the same commit, so it isn't reported twice
+    let in_submodule: Vec<_> = verdict.problems.iter()
+        .filter_map(|problem| match problem {
+            BackupProblem::InSubmodule { path, problem } => Some((path.as_str(), &**problem)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(in_submodule.len(), 1, "{:?}", in_submodule);
+    assert_eq!(in_submodule[0].0, "vendor/lib");
+    assert!(matches!(in_submodule[0].1, BackupProblem::UncommittedChanges { .. }));
+
+    // a new commit checked out in the submodule is a change the superproject hasn't recorded yet
+    git(&lib, &["commit", "--allow-empty", "-m", "another fix"]);
+    assert_eq!(get_status_path(&app).unwrap().files.modified, 1);
+}
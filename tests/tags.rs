@@ -0,0 +1,96 @@
+mod common;
+
+use virtual_repo_hub::{get_status_path, UnpushedTag};
+use virtual_repo_hub::backup::{check_backup, BackupPolicy, BackupProblem};
+
+use common::git;
+
+use tempfile::tempdir;
+
+#[test]
+fn finds_tags_not_in_remote_branches() {
+    let root = tempdir().unwrap();
+    git(root.path(), &["init", "-b", "master", "upstream"]);
+    let upstream = root.path().join("upstream");
+    git(&upstream, &["commit", "--allow-empty", "-m", "first"]);
+    git(root.path(), &["clone", "-q", "upstream", "clone"]);
+    let clone = root.path().join("clone");
+
+    // a tag of a pushed commit is safe
+    git(&clone, &["tag", "pushed"]);
+    assert!(get_status_path(&clone).unwrap().unpushed_tags.is_empty());
+
+    git(&clone, &["commit", "--allow-empty", "-m", "release"]);
+    git(&clone, &["tag", "-a", "-m", "release", "v1.0"]);
+    let id = git(&clone, &["rev-parse", "v1.0"]);
+
+    let status = get_status_path(&clone).unwrap();
+    let tag = UnpushedTag {
+        name: "v1.0".to_string(),
+        id,
+        annotated: true,
+        merged_in_remote: false,
+        missing_on: Vec::new(),
+        differs_on: Vec::new(),
+    };
+    assert_eq!(status.unpushed_tags, vec![tag.clone()]);
+
+    let verdict = check_backup(&status, &BackupPolicy::default());
+    let problem = BackupProblem::UnpushedTag(tag);
+    assert_eq!(problem.to_string(), "tag v1.0: not in any remote branch");
+    assert!(verdict.problems.contains(&problem));
+
+    git(&clone, &["push", "-q", "origin", "master:release"]);
+    assert!(get_status_path(&clone).unwrap().unpushed_tags.is_empty());
+}
+
+#[test]
+fn compares_tags_with_tracking_refs() {
+    let root = tempdir().unwrap();
+    git(root.path(), &["init", "-b", "master", "upstream"]);
+    let upstream = root.path().join("upstream");
+    git(&upstream, &["commit", "--allow-empty", "-m", "first"]);
+    git(&upstream, &["tag", "-a", "-m", "first", "v1.0"]);
+    git(root.path(), &["clone", "-q", "upstream", "clone"]);
+    let clone = root.path().join("clone");
+    git(&clone, &["config", "--add", "remote.origin.fetch", "+refs/tags/*:refs/remotes/origin/tags/*"]);
+    git(&clone, &["fetch", "-q", "origin"]);
+    assert!(get_status_path(&clone).unwrap().unpushed_tags.is_empty());
+
+    // retagging the same commit leaves it merged, but the tag objects differ
+    git(&clone, &["tag", "-f", "-a", "-m", "retagged", "v1.0"]);
+    git(&clone, &["tag", "v1.1"]);
+
+    let status = get_status_path(&clone).unwrap();
+    let tags: Vec<_> = status.unpushed_tags.iter()
+        .map(|tag| (tag.name.as_str(), tag.merged_in_remote, &tag.missing_on, &tag.differs_on))
+        .collect();
+    let origin = vec!["origin".to_string()];
+    assert_eq!(tags, vec![
+        ("v1.0", true, &Vec::new(), &origin),
+        ("v1.1", true, &origin, &Vec::new()),
+    ]);
+    assert!(status.unpushed_tags[0].annotated);
+    assert!(!status.unpushed_tags[1].annotated);
+}
+
+#[test]
+fn fetching_tags_onto_themselves_is_not_pushing() {
+    let root = tempdir().unwrap();
+    git(root.path(), &["init", "-b", "master", "upstream"]);
+    let upstream = root.path().join("upstream");
+    git(&upstream, &["commit", "--allow-empty", "-m", "first"]);
+    git(root.path(), &["clone", "-q", "upstream", "clone"]);
+    let clone = root.path().join("clone");
+    git(&clone, &["config", "--add", "remote.origin.fetch", "+refs/tags/*:refs/tags/*"]);
+
+    git(&clone, &["commit", "--allow-empty", "-m", "release"]);
+    git(&clone, &["tag", "v1.0"]);
+
+    let status = get_status_path(&clone).unwrap();
+    let tags: Vec<_> = status.unpushed_tags.iter()
+        .map(|tag| (tag.name.as_str(), tag.merged_in_remote, tag.missing_on.is_empty()))
+        .collect();
+    assert_eq!(tags, vec![("v1.0", false, true)]);
+    assert!(!check_backup(&status, &BackupPolicy::default()).backed_up);
+}
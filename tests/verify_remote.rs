@@ -0,0 +1,127 @@
+mod common;
+
+use virtual_repo_hub::{get_status_with_options, open_repo, BranchStatus, StatusOptions};
+use virtual_repo_hub::backup::{check_backup, BackupPolicy, BackupProblem};
+
+use common::git;
+
+use tempfile::tempdir;
+
+#[test]
+fn reports_stale_tracking_refs() {
+    let root = tempdir().unwrap();
+    let upstream = root.path().join("upstream.git");
+    let url = format!("file://{}", upstream.display());
+    git(root.path(), &["init", "--bare", "upstream.git"]);
+    git(root.path(), &["clone", &url, "local"]);
+    git(root.path(), &["clone", &url, "other"]);
+
+    let local = root.path().join("local");
+    let other = root.path().join("other");
+    git(&local, &["commit", "--allow-empty", "-m", "first"]);
+    git(&local, &["push", "origin", "HEAD:master", "HEAD:old"]);
+    git(&local, &["fetch"]);
+    git(&local, &["branch", "--set-upstream-to", "origin/master"]);
+
+    // someone else moves master on and deletes a branch, without this clone fetching
+    git(&other, &["fetch"]);
+    git(&other, &["checkout", "master"]);
+    git(&other, &["commit", "--allow-empty", "-m", "second"]);
+    git(&other, &["push", "origin", "master", ":old"]);
+
+    let mut repo = open_repo(&local).unwrap();
+    let status = get_status_with_options(&mut repo, &StatusOptions::default()).unwrap();
+    assert!(status.remotes[0].verification.is_none());
+    assert!(check_backup(&status, &BackupPolicy::default()).backed_up);
+
+    let options = StatusOptions {
+        verify_remotes: true,
+        ..StatusOptions::default()
+    };
+    let status = get_status_with_options(&mut repo, &options).unwrap();
+    let verification = status.remotes[0].verification.as_ref().unwrap();
+    assert_eq!(verification.error, None);
+    let stale: Vec<_> = verification.stale_refs.iter()
+        .map(|stale| (stale.local_ref.as_str(), stale.recorded.is_some(), stale.actual.is_some()))
+        .collect();
+    assert_eq!(stale, vec![
+        ("refs/remotes/origin/master", true, true),
+        ("refs/remotes/origin/old", true, false),
+    ]);
+    match &status.branches["master"] {
+        BranchStatus::TrackingBranch(tracking) => assert!(tracking.stale),
+        other => panic!("expected a tracking branch, got {:?}", other),
+    }
+
+    let verdict = check_backup(&status, &BackupPolicy::default());
+    assert_eq!(verdict.problems, vec![BackupProblem::StaleUpstream {
+        branch: "master".to_string(),
+        upstream: "origin/master".to_string(),
+    }]);
+
+    // fetching brings everything up to date again
+    git(&local, &["fetch", "--prune"]);
+    let status = get_status_with_options(&mut repo, &options).unwrap();
+    assert!(status.remotes[0].verification.as_ref().unwrap().stale_refs.is_empty());
+}
+
+#[test]
+fn unreachable_remote_is_a_warning() {
+    let root = tempdir().unwrap();
+    git(root.path(), &["init", "repo"]);
+    let path = root.path().join("repo");
+    let missing = format!("file://{}", root.path().join("missing.git").display());
+    git(&path, &["remote", "add", "origin", &missing]);
+
+    let options = StatusOptions {
+        verify_remotes: true,
+        ..StatusOptions::default()
+    };
+    let status = get_status_with_options(&mut open_repo(&path).unwrap(), &options).unwrap();
+    assert!(status.remotes[0].verification.as_ref().unwrap().error.is_some());
+
+    let verdict = check_backup(&status, &BackupPolicy::default());
+    assert!(verdict.warnings.iter()
+        .any(|warning| matches!(warning, BackupProblem::UnverifiedRemote { .. })));
+}
+
+#[test]
+fn mirror_branches_need_verifying() {
+    let root = tempdir().unwrap();
+    git(root.path(), &["init", "-q", "-b", "master", "upstream"]);
+    let upstream = root.path().join("upstream");
+    git(&upstream, &["commit", "--allow-empty", "-m", "first"]);
+    git(&upstream, &["tag", "v1.0"]);
+    git(root.path(), &["clone", "-q", "--mirror", "upstream", "mirror.git"]);
+    let mirror = root.path().join("mirror.git");
+
+    let options = StatusOptions {
+        verify_remotes: true,
+        ..StatusOptions::default()
+    };
+    let mut repo = open_repo(&mirror).unwrap();
+    let status = get_status_with_options(&mut repo, &options).unwrap();
+    assert_eq!(status.branches["master"], BranchStatus::MirrorBranch {
+        remote: "origin".to_string(),
+        verified: true,
+    });
+    // a verified branch counts as a remote branch, so tags of its commits are pushed too
+    assert!(status.unpushed_tags.is_empty());
+    assert!(check_backup(&status, &BackupPolicy::default()).backed_up);
+
+    // without asking the remote there is no telling what was pushed into the mirror
+    let status = get_status_with_options(&mut repo, &StatusOptions::default()).unwrap();
+    let problem = BackupProblem::UnverifiedMirrorBranch {
+        branch: "master".to_string(),
+        remote: "origin".to_string(),
+    };
+    assert!(check_backup(&status, &BackupPolicy::default()).problems.contains(&problem));
+
+    // a commit pushed into the mirror that never reached the upstream
+    git(root.path(), &["clone", "-q", "mirror.git", "work"]);
+    let work = root.path().join("work");
+    git(&work, &["commit", "--allow-empty", "-m", "second"]);
+    git(&work, &["push", "-q", "origin", "master"]);
+    let status = get_status_with_options(&mut repo, &options).unwrap();
+    assert!(check_backup(&status, &BackupPolicy::default()).problems.contains(&problem));
+}
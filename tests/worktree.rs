@@ -0,0 +1,116 @@
+mod common;
+
+use virtual_repo_hub::{get_status_path, FileStatus, Head, OperationKind};
+use virtual_repo_hub::backup::{check_backup, BackupPolicy, BackupProblem};
+use virtual_repo_hub::discover::{discover_repos, DiscoverOptions};
+
+use common::{git, try_git};
+
+use tempfile::tempdir;
+
+use std::fs;
+use std::path::Path;
+
+#[test]
+fn reports_linked_worktrees() {
+    let root = tempdir().unwrap();
+    git(root.path(), &["init", "-q", "-b", "master", "repo"]);
+    let repo = root.path().join("repo");
+    git(&repo, &["commit", "--allow-empty", "-m", "first"]);
+    git(&repo, &["worktree", "add", "-q", "-b", "feature", "../feature"]);
+    git(&repo, &["worktree", "add", "-q", "--detach", "../detached"]);
+    git(&repo, &["worktree", "lock", "../detached"]);
+    let feature = root.path().join("feature");
+    let detached = root.path().join("detached");
+    let commit = git(&detached, &["rev-parse", "HEAD"]);
+    fs::write(feature.join("wip.txt"), "wip").unwrap();
+
+    let status = get_status_path(&repo).unwrap();
+    let worktrees: Vec<_> = status.worktrees.iter()
+        .map(|worktree| (worktree.name.as_str(), worktree.path.as_str(), worktree.locked))
+        .collect();
+    assert_eq!(worktrees, vec![
+        ("detached", detached.to_str().unwrap(), true),
+        ("feature", feature.to_str().unwrap(), false),
+    ]);
+    assert_eq!(status.worktrees[0].head, Some(Head::Detached { commit, merged_in_remote: false }));
+    assert!(status.worktrees[0].files.is_clean());
+    assert_eq!(status.worktrees[1].head, Some(Head::Branch { name: "feature".to_string() }));
+    assert_eq!(status.worktrees[1].files.untracked, 1);
+    // the main working tree isn't affected by the others
+    assert!(status.files.is_clean());
+
+    // a worktree's own status doesn't list its siblings
+    assert!(get_status_path(&feature).unwrap().worktrees.is_empty());
+
+    let verdict = check_backup(&status, &BackupPolicy::default());
+    let in_worktree: Vec<_> = verdict.problems.iter()
+        .filter_map(|problem| match problem {
+            BackupProblem::InWorktree { path, problem } => Some((path.as_str(), &**problem)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(in_worktree.len(), 2, "{:?}", in_worktree);
+    assert_eq!(in_worktree[0].0, detached.to_str().unwrap());
+    assert!(matches!(in_worktree[0].1, BackupProblem::DetachedHead { .. }));
+    assert_eq!(in_worktree[1].0, feature.to_str().unwrap());
+    assert!(matches!(in_worktree[1].1, BackupProblem::UncommittedChanges { .. }));
+
+    // a deleted worktree has nothing left to lose
+    fs::remove_dir_all(&feature).unwrap();
+    let status = get_status_path(&repo).unwrap();
+    assert!(status.worktrees[1].missing);
+    assert_eq!(status.worktrees[1].head, None);
+    assert_eq!(status.worktrees[1].files, FileStatus::default());
+}
+
+#[test]
+fn reports_operation_in_worktree() {
+    let root = tempdir().unwrap();
+    git(root.path(), &["init", "-q", "-b", "master", "repo"]);
+    let repo = root.path().join("repo");
+    fs::write(repo.join("a.txt"), "one").unwrap();
+    git(&repo, &["add", "a.txt"]);
+    git(&repo, &["commit", "-q", "-m", "one"]);
+    git(&repo, &["worktree", "add", "-q", "-b", "topic", "../topic"]);
+    let topic = root.path().join("topic");
+    fs::write(topic.join("a.txt"), "topic").unwrap();
+    git(&topic, &["commit", "-q", "-am", "topic"]);
+    fs::write(repo.join("a.txt"), "master").unwrap();
+    git(&repo, &["commit", "-q", "-am", "master"]);
+
+    assert!(!try_git(&topic, &["merge", "-q", "master"]));
+
+    let status = get_status_path(&repo).unwrap();
+    assert_eq!(status.operation, None);
+    let operation = status.worktrees[0].operation.as_ref().unwrap();
+    assert_eq!(operation.kind, OperationKind::Merge);
+    assert_eq!(operation.branch.as_deref(), Some("topic"));
+    assert_eq!(operation.conflicted, vec!["a.txt"]);
+    assert_eq!(status.worktrees[0].files.conflicted, 1);
+}
+
+#[test]
+fn discovery_skips_worktrees_of_found_repos() {
+    let root = tempdir().unwrap();
+    git(root.path(), &["init", "-q", "-b", "master", "repo"]);
+    let repo = root.path().join("repo");
+    git(&repo, &["commit", "--allow-empty", "-m", "first"]);
+    git(&repo, &["worktree", "add", "-q", "-b", "feature", "../feature"]);
+    git(root.path(), &["init", "-q", "--bare", "bare.git"]);
+    let bare = root.path().join("bare.git");
+    git(&repo, &["push", "-q", &bare.to_string_lossy(), "master"]);
+    git(&bare, &["worktree", "add", "-q", "../from-bare", "master"]);
+
+    let found = |root: &Path| -> Vec<_> {
+        discover_repos(root, &DiscoverOptions::default())
+            .into_iter()
+            .map(|found| found.unwrap())
+            .collect()
+    };
+    assert_eq!(found(root.path()), vec![bare, repo]);
+
+    // the worktree is still found when the repo that owns it is out of reach
+    let feature = root.path().join("feature");
+    assert_eq!(found(&feature), vec![feature]);
+}